@@ -0,0 +1,209 @@
+use crate::consts::BINARY_SIGNATURE_ALZ4;
+
+use crate::error::{ResourceError, FileFormat};
+
+use std::cmp;
+
+// alz4 header: signature, uncompressed size, compressed size, version
+pub const ALZ4_HEADER_SIZE: usize = 16;
+const ALZ4_VERSION: u32 = 1;
+
+const LZ4_HASH_LOG: u32 = 16;
+const LZ4_MAX_OFFSET: usize = 65535;
+const LZ4_MF_LIMIT: usize = 12;
+const LZ4_LAST_LITERALS: usize = 5;
+
+fn read_u32(source: &[u8], offset: usize) -> Result<u32, ResourceError> {
+    let bytes = source.get(offset..offset + 4).ok_or(ResourceError::CorruptedData(FileFormat::Alz4))?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// size of data after decompression, taken from alz4 header
+pub fn uncompressed_size(source: &[u8]) -> Result<u32, ResourceError> {
+    let signature = read_u32(source, 0)?;
+    if signature != BINARY_SIGNATURE_ALZ4 {
+        return Err(ResourceError::BadSignature { format: FileFormat::Alz4, signature });
+    }
+    read_u32(source, 4)
+}
+
+// decompress alz4 wrapped data (header + lz4 block)
+pub fn decompress(source: &[u8]) -> Result<Vec<u8>, ResourceError> {
+    let size = uncompressed_size(source)? as usize;
+    let compressed_size = read_u32(source, 8)? as usize;
+    let block = source.get(ALZ4_HEADER_SIZE..ALZ4_HEADER_SIZE + compressed_size).ok_or(ResourceError::CorruptedData(FileFormat::Alz4))?;
+    let data = decompress_block(block, size)?;
+    if data.len() != size {
+        return Err(ResourceError::CorruptedData(FileFormat::Alz4));
+    }
+    Ok(data)
+}
+
+// read lz4 extended length (sequence of 255 bytes terminated by byte < 255)
+fn read_length(source: &[u8], pos: &mut usize, mut length: usize) -> Result<usize, ResourceError> {
+    loop {
+        let byte = *source.get(*pos).ok_or(ResourceError::CorruptedData(FileFormat::Alz4))?;
+        *pos += 1;
+        length += byte as usize;
+        if byte != 255 {
+            return Ok(length);
+        }
+    }
+}
+
+// decompress raw lz4 block
+pub fn decompress_block(source: &[u8], size: usize) -> Result<Vec<u8>, ResourceError> {
+    // size comes from header, so don't trust it more than lz4 ratio allows
+    let mut dest: Vec<u8> = Vec::with_capacity(cmp::min(size, source.len().saturating_mul(255)));
+    let mut pos: usize = 0;
+    while pos < source.len() {
+        let token = source[pos];
+        pos += 1;
+
+        let mut literal_length = (token >> 4) as usize;
+        if literal_length == 15 {
+            literal_length = read_length(source, &mut pos, literal_length)?;
+        }
+        let literals = source.get(pos..pos + literal_length).ok_or(ResourceError::CorruptedData(FileFormat::Alz4))?;
+        dest.extend_from_slice(literals);
+        pos += literal_length;
+
+        // last sequence contains only literals
+        if pos >= source.len() {
+            break;
+        }
+
+        let offset = source.get(pos..pos + 2).ok_or(ResourceError::CorruptedData(FileFormat::Alz4))?;
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+        pos += 2;
+        if offset == 0 || offset > dest.len() {
+            return Err(ResourceError::CorruptedData(FileFormat::Alz4));
+        }
+
+        let mut match_length = (token & 0x0F) as usize;
+        if match_length == 15 {
+            match_length = read_length(source, &mut pos, match_length)?;
+        }
+        match_length += 4;
+        if dest.len() + match_length > size {
+            return Err(ResourceError::CorruptedData(FileFormat::Alz4));
+        }
+
+        // match can overlap with bytes being copied
+        let start = dest.len() - offset;
+        for i in 0..match_length {
+            let byte = dest[start + i];
+            dest.push(byte);
+        }
+    }
+    Ok(dest)
+}
+
+
+// compress data to alz4 wrapped lz4 block
+pub fn compress(source: &[u8]) -> Vec<u8> {
+    let block = compress_block(source);
+    let mut dest: Vec<u8> = Vec::with_capacity(ALZ4_HEADER_SIZE + block.len());
+    dest.extend_from_slice(&BINARY_SIGNATURE_ALZ4.to_le_bytes());
+    dest.extend_from_slice(&(source.len() as u32).to_le_bytes());
+    dest.extend_from_slice(&(block.len() as u32).to_le_bytes());
+    dest.extend_from_slice(&ALZ4_VERSION.to_le_bytes());
+    dest.extend_from_slice(&block);
+    dest
+}
+
+// write lz4 extended length for values >= 15
+fn write_length(dest: &mut Vec<u8>, mut length: usize) {
+    while length >= 255 {
+        dest.push(255);
+        length -= 255;
+    }
+    dest.push(length as u8);
+}
+
+fn write_sequence(dest: &mut Vec<u8>, literals: &[u8], offset: usize, match_length: usize) {
+    let literal_token = cmp::min(literals.len(), 15) as u8;
+    let match_token = cmp::min(match_length - 4, 15) as u8;
+    dest.push((literal_token << 4) | match_token);
+    if literals.len() >= 15 {
+        write_length(dest, literals.len() - 15);
+    }
+    dest.extend_from_slice(literals);
+    dest.extend_from_slice(&(offset as u16).to_le_bytes());
+    if match_length - 4 >= 15 {
+        write_length(dest, match_length - 4 - 15);
+    }
+}
+
+fn write_last_literals(dest: &mut Vec<u8>, literals: &[u8]) {
+    dest.push((cmp::min(literals.len(), 15) as u8) << 4);
+    if literals.len() >= 15 {
+        write_length(dest, literals.len() - 15);
+    }
+    dest.extend_from_slice(literals);
+}
+
+// compress data to raw lz4 block (greedy hash chain of length 1)
+pub fn compress_block(source: &[u8]) -> Vec<u8> {
+    let mut dest: Vec<u8> = Vec::with_capacity(source.len() / 2 + 16);
+    let mut table = vec![0usize; 1 << LZ4_HASH_LOG];
+    let mut anchor: usize = 0;
+    let mut pos: usize = 0;
+
+    // lz4 requires last match to start 12 bytes and end 5 bytes before end of block
+    if source.len() > LZ4_MF_LIMIT {
+        let match_limit = source.len() - LZ4_MF_LIMIT;
+        let end_limit = source.len() - LZ4_LAST_LITERALS;
+        while pos < match_limit {
+            let sequence = u32::from_le_bytes([source[pos], source[pos + 1], source[pos + 2], source[pos + 3]]);
+            let hash = (sequence.wrapping_mul(2654435761) >> (32 - LZ4_HASH_LOG)) as usize;
+            let candidate = table[hash];
+            table[hash] = pos + 1;
+
+            if candidate > 0 {
+                let candidate = candidate - 1;
+                if pos - candidate <= LZ4_MAX_OFFSET && source[candidate..candidate + 4] == source[pos..pos + 4] {
+                    let mut match_length: usize = 4;
+                    while pos + match_length < end_limit && source[candidate + match_length] == source[pos + match_length] {
+                        match_length += 1;
+                    }
+                    write_sequence(&mut dest, &source[anchor..pos], pos - candidate, match_length);
+                    pos += match_length;
+                    anchor = pos;
+                    continue;
+                }
+            }
+            pos += 1;
+        }
+    }
+    write_last_literals(&mut dest, &source[anchor..]);
+    dest
+}
+
+
+#[test]
+fn decompress_alz4_data() {
+    let block: Vec<u8> = vec![0x35, b'a', b'b', b'c', 0x03, 0x00, 0x30, b'X', b'Y', b'Z'];
+    let mut data: Vec<u8> = Vec::new();
+    data.extend_from_slice(&BINARY_SIGNATURE_ALZ4.to_le_bytes());
+    data.extend_from_slice(&15u32.to_le_bytes());
+    data.extend_from_slice(&(block.len() as u32).to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&block);
+    assert_eq!(decompress(&data).unwrap(), b"abcabcabcabcXYZ");
+}
+
+#[test]
+fn compress_alz4_data() {
+    let mut source: Vec<u8> = Vec::new();
+    for i in 0..20000u32 {
+        source.extend_from_slice(format!("<unit id=\"{}\">", i % 97).as_bytes());
+        source.push((i.wrapping_mul(2654435761) >> 24) as u8);
+    }
+    let data = compress(&source);
+    assert!(data.len() < source.len());
+    assert_eq!(uncompressed_size(&data).unwrap() as usize, source.len());
+    assert_eq!(decompress(&data).unwrap(), source);
+    assert_eq!(decompress(&compress(b"")).unwrap(), b"");
+    assert_eq!(decompress(&compress(b"short")).unwrap(), b"short");
+}
@@ -1,37 +1,52 @@
 use crate::ddt::DdtFile;
+use crate::alz4;
+use crate::l33t;
+use crate::sound;
+use crate::filter::{self, EntryFilter};
+use crate::xmb::{self, XmbFile};
+use crate::error::{ResourceError, FileFormat};
+use crate::datetime::DateTime;
+use crate::hash::{ContentHash, EntryHash};
+use crate::loc::ERR_ENTRY_OUT_OF_DATA;
+use crate::loc::ERR_ENTRY_OVERLAP;
+use crate::loc::ERR_EMPTY_ENTRY;
+use crate::loc::ERR_DUPLICATE_ENTRY;
+use crate::loc::ERR_ENCODING_MISMATCH;
 
 use crate::consts::BINARY_SIGNATURE_DDT;
 use crate::consts::BINARY_BAR_MAGIC;
 use crate::consts::BAR_VERSION_AOE3DE;
 use crate::consts::BAR_VERSION_AOE3;
+use crate::consts::BAR_HEADER_SIZE_AOE3DE;
+use crate::consts::BAR_HEADER_SIZE_AOE3;
 use crate::consts::BINARY_SIGNATURE_ALZ4;
 use crate::consts::BINARY_SIGNATURE_L33T;
-use crate::consts::BINARY_SIGNATURE_WAV_DECODED;
 use crate::consts::BINARY_SIGNATURE_WAV_ENCODED;
 use crate::consts::BINARY_SIGNATURE_BAR;
 
-use crate::loc::ERR_NOT_MATCHED_ENTRY_COUNT;
-use crate::loc::ERR_NOT_VALID_BAR_MAGIC;
-use crate::loc::ERR_NOT_SUPPORTED_BAR_VERSION;
-use crate::loc::ERR_NOT_VALID_BAR_SIGNATURE;
-use crate::loc::ERR_BAR_NOT_FOUND;
-use crate::loc::ERR_NOT_VALID_DECODED_WAV_SIGNATURE;
-use std::process::Command;
 use std::{
-    env,
+    cmp,
     thread,
-    error::Error,
-    path::{PathBuf},
+    panic,
+    collections::{HashMap, HashSet},
+    sync::{Mutex, PoisonError},
+    sync::atomic::{AtomicUsize, AtomicBool, Ordering},
+    path::{Component, Path, PathBuf},
     time::{Instant,SystemTime},
-    convert::TryInto,
     fs::{self, File},
     io::{self, Read, Write, BufReader, BufWriter, SeekFrom, Seek},
 };
 
 
 
-pub struct BarFile {
-    pub bar_path: PathBuf, // path to opened bar file []
+// source of archive data: file, in-memory buffer or any other seekable stream
+pub trait BarReader: Read + Seek + Send {}
+
+impl<R: Read + Seek + Send> BarReader for R {}
+
+pub struct BarFile<'r> {
+    pub bar_path: PathBuf, // path to opened bar file. empty if archive is read from memory []
+    reader: Mutex<Box<dyn BarReader + 'r>>, // archive data, entries are read from it on demand. may borrow from outer archive []
     signature: u32,    // signature [de, legacy]
     pub version: u32, // version of bar file [de, legacy]
     magic: u32, // magic number 1 [de, legacy]
@@ -46,6 +61,8 @@ pub struct BarFile {
     pub root_path: Vec<u8>, // root path [de, legacy]
     root_file_count: u32, // count of files in root. should be same as file_count [de, legacy]
     pub entries: Vec<BarEntry>, // bar entries [de, legacy]
+    modified: bool, // entries were added, replaced, deleted or renamed since archive was read []
+    pub trust_entry_paths: bool, // extract entry names as they are, without path traversal checks []
 }
 
 #[derive(Clone)]
@@ -53,7 +70,7 @@ pub struct BarEntry {
     //pub bar_path: PathBuf, // path to opened bar file []
     //pub root_path: Vec<u8>, // path to root directory of entry []
     pub offset: u64, // offset. u64 for DE and u32 for legacy [de, legacy]
-    file_size1: u32, // uncompressed size [de, legacy]
+    pub file_size1: u32, // uncompressed size [de, legacy]
     pub file_size2: u32, // real size of binary [de, legacy]
     file_size3: u32, // some dublicate size [de]
     year: u16, // last write time [legacy]
@@ -67,11 +84,148 @@ pub struct BarEntry {
     file_name_length: u32, // entry file name length [de, legacy]
     pub file_name: Vec<u8>, // entry file name [de, legacy]
     pub is_encoded: u32, // type of encoding [de]
+    new_data: Option<Vec<u8>>, // data added or replaced since archive was read. not written yet []
 }
 
 impl BarEntry {
-    pub fn get_path(&self, managed_path: &PathBuf, root_path: &[u8]) -> Result<PathBuf, Box<dyn Error>> {
-        return Ok(managed_path.join(&BarFile::vec_u8_to_string_u16(root_path)?).join(BarFile::vec_u8_to_string_u16(&self.file_name)?));
+    pub fn get_file_name(&self) -> Result<String, ResourceError> {
+        return BarFile::vec_u8_to_string_u16(&self.file_name);
+    }
+
+    // file name for error messages, broken utf-16 is replaced
+    pub fn get_file_name_lossy(&self) -> String {
+        let chars: Vec<u16> = self.file_name.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        return String::from_utf16_lossy(&chars);
+    }
+
+    // last write time, stored only in legacy archives
+    pub fn get_date_time(&self) -> DateTime {
+        return DateTime {
+            year: self.year,
+            month: self.month,
+            day_of_week: self.day_of_week,
+            day: self.day,
+            hour: self.hour,
+            minute: self.minute,
+            second: self.second,
+            msecond: self.msecond,
+        };
+    }
+
+    pub fn set_date_time(&mut self, date_time: DateTime) {
+        self.year = date_time.year;
+        self.month = date_time.month;
+        self.day_of_week = date_time.day_of_week;
+        self.day = date_time.day;
+        self.hour = date_time.hour;
+        self.minute = date_time.minute;
+        self.second = date_time.second;
+        self.msecond = date_time.msecond;
+    }
+
+    // names in archive are separated with backslashes, every part becomes separate path component on any os.
+    // names leading out of managed_path (.., absolute or drive paths) are rejected
+    pub fn get_path(&self, managed_path: &Path, root_path: &[u8]) -> Result<PathBuf, ResourceError> {
+        return self.join_path(managed_path, root_path, true);
+    }
+
+    // same as get_path but without checks, only for trusted archives
+    pub fn get_trusted_path(&self, managed_path: &Path, root_path: &[u8]) -> Result<PathBuf, ResourceError> {
+        return self.join_path(managed_path, root_path, false);
+    }
+
+    fn join_path(&self, managed_path: &Path, root_path: &[u8], check: bool) -> Result<PathBuf, ResourceError> {
+        let mut path = managed_path.to_path_buf();
+        for name in [BarFile::vec_u8_to_string_u16(root_path)?, BarFile::vec_u8_to_string_u16(&self.file_name)?] {
+            if check && name.starts_with(['\\', '/']) {
+                return Err(ResourceError::UnsafeEntryPath(name));
+            }
+            for component in name.split(['\\', '/']).filter(|component| !component.is_empty()) {
+                if check && !is_safe_path_component(component) {
+                    return Err(ResourceError::UnsafeEntryPath(name));
+                }
+                path.push(component);
+            }
+        }
+        return Ok(path);
+    }
+
+    // entry record of files table, every field is written as it was read
+    pub fn to_bytes(&self, version: u32) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        if version == BAR_VERSION_AOE3 {
+            bytes.extend_from_slice(&(self.offset as u32).to_le_bytes());
+            bytes.extend_from_slice(&self.file_size1.to_le_bytes());
+            bytes.extend_from_slice(&self.file_size2.to_le_bytes());
+            bytes.extend_from_slice(&self.year.to_le_bytes());
+            bytes.extend_from_slice(&self.month.to_le_bytes());
+            bytes.extend_from_slice(&self.day_of_week.to_le_bytes());
+            bytes.extend_from_slice(&self.day.to_le_bytes());
+            bytes.extend_from_slice(&self.hour.to_le_bytes());
+            bytes.extend_from_slice(&self.minute.to_le_bytes());
+            bytes.extend_from_slice(&self.second.to_le_bytes());
+            bytes.extend_from_slice(&self.msecond.to_le_bytes());
+        }
+        else {
+            bytes.extend_from_slice(&self.offset.to_le_bytes());
+            bytes.extend_from_slice(&self.file_size1.to_le_bytes());
+            bytes.extend_from_slice(&self.file_size2.to_le_bytes());
+            bytes.extend_from_slice(&self.file_size3.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.file_name_length.to_le_bytes());
+        bytes.extend_from_slice(&self.file_name);
+        if version == BAR_VERSION_AOE3DE {
+            bytes.extend_from_slice(&self.is_encoded.to_le_bytes());
+        }
+        return bytes;
+    }
+}
+
+// reader bounded to offset..offset + file_size2 of one entry. archive reader is locked
+// only for the duration of each read, so several entry readers can be used at once
+pub struct EntryReader<'a, 'r> {
+    bar: &'a BarFile<'r>,
+    new_data: Option<&'a [u8]>,
+    offset: u64,
+    size: u64,
+    position: u64,
+}
+
+impl<'a, 'r> Read for EntryReader<'a, 'r> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size.saturating_sub(self.position);
+        let length = cmp::min(buf.len() as u64, remaining) as usize;
+        if length == 0 {
+            return Ok(0);
+        }
+        if let Some(data) = self.new_data {
+            let start = self.position as usize;
+            buf[..length].copy_from_slice(&data[start..start + length]);
+            self.position += length as u64;
+            return Ok(length);
+        }
+        let mut reader = self.bar.reader.lock().unwrap_or_else(PoisonError::into_inner);
+        reader.seek(SeekFrom::Start(self.offset + self.position))?;
+        let length = reader.read(&mut buf[..length])?;
+        self.position += length as u64;
+        return Ok(length);
+    }
+}
+
+impl<'a, 'r> Seek for EntryReader<'a, 'r> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(delta) => self.size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                return Ok(position);
+            },
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position")),
+        }
     }
 }
 
@@ -81,10 +235,29 @@ struct RawBarEntry{
     modified_datetime: SystemTime,
 }
 
-fn get_raw_bar_entries_in_directory(dir: &PathBuf) -> Result<Vec<RawBarEntry>, Box<dyn Error>> {
-    let items = fs::read_dir(dir)?
+// plain file or folder name. ':' is rejected on every os, so drive paths and streams of windows don't pass on linux either
+fn is_safe_path_component(component: &str) -> bool {
+    let mut components = Path::new(component).components();
+    return matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) && !component.contains(':');
+}
+
+// path relative to archive root as it is stored in archive: components joined with backslashes
+fn get_entry_name(path: &Path, dir: &Path) -> Result<String, ResourceError> {
+    let relative_path = path.strip_prefix(dir).map_err(|_| ResourceError::InvalidPath(path.to_path_buf()))?;
+    let mut components: Vec<&str> = Vec::new();
+    for component in relative_path.components() {
+        components.push(component.as_os_str().to_str().ok_or_else(|| ResourceError::InvalidPath(path.to_path_buf()))?);
+    }
+    return Ok(components.join("\\"));
+}
+
+fn get_raw_bar_entries_in_directory(dir: &PathBuf) -> Result<Vec<RawBarEntry>, ResourceError> {
+    let mut items = fs::read_dir(dir)?
     .map(|res| res.map(|e| e.path()))
     .collect::<Result<Vec<_>, io::Error>>()?;
+    // read_dir order depends on file system. sort like ntfs does (case-insensitive, by upper case),
+    // so archives created on any os have same files table
+    items.sort_by_cached_key(|item| item.file_name().map(|name| name.to_string_lossy().to_uppercase()));
 
     let mut raw_entries: Vec<RawBarEntry> = Vec::new();
     for item in items{
@@ -102,173 +275,689 @@ fn get_raw_bar_entries_in_directory(dir: &PathBuf) -> Result<Vec<RawBarEntry>, B
     return Ok(raw_entries);
 }
 
-pub fn get_file_signature(source: &[u8], size: usize) -> u32 {
+// end of file inside files table means that table is truncated
+fn table_error(error: io::Error, offset: u64, index: usize) -> ResourceError {
+    if error.kind() == io::ErrorKind::UnexpectedEof {
+        return ResourceError::TruncatedTable { offset: offset, index: index as u32 };
+    }
+    return ResourceError::Io(error);
+}
+
+// callers check that source has at least 4 bytes
+pub(crate) fn get_file_signature(source: &[u8], size: usize) -> u32 {
     let mut data = [0u8; 4];
     data.copy_from_slice(&source[0..size]);
     return u32::from_le_bytes(data);
 }
 
 
-impl BarFile{
+impl<'r> BarFile<'r> {
     // convert Vec<u8> to utf-16-le string
-    fn vec_u8_to_string_u16(source: &[u8]) -> Result<String, Box<dyn Error>> {
+    fn vec_u8_to_string_u16(source: &[u8]) -> Result<String, ResourceError> {
         let mut dest = vec![0u16; 0];
         for i in 0..source.len() / 2 {
             let n: u16 = ((source[2 * i + 1] as u16) << 8) | source[2 * i] as u16;
             dest.push(n);
         }
 
-        Ok(String::from_utf16(&dest)?)
+        String::from_utf16(&dest).map_err(|_| ResourceError::InvalidEntryName)
     }
 
     // decode encrypted sound files
-    pub fn decode_sound(source: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    pub fn decode_sound(source: &[u8]) -> Result<Vec<u8>, ResourceError> {
+        return sound::decode(source);
+    }
 
-        let qword8: u64 = 0x23966BA95E28C33F;
-        let qword10: u64 = 0x39BAE3441DB35873;
-        let mut qword18: u64 = 0x2AF92545ADDE0B65;
+    pub fn encode_sound(source: &[u8]) -> Result<Vec<u8>, ResourceError> {
+        return sound::encode(source);
+    }
 
-        let mut decoded_data:Vec<u8> = Vec::new();
-        let non_padded_size = source.len();
-        let padding_length = (8 - non_padded_size % 8) % 8;
-        let padded_data = &mut source.to_vec();
+    // check if data is already wrapped with alz4, l33t or sound encoding
+    fn is_encoded_data(data: &[u8]) -> bool {
+        if data.len() < 4 {
+            return false;
+        }
+        return matches!(get_file_signature(data, 4), BINARY_SIGNATURE_ALZ4 | BINARY_SIGNATURE_L33T | BINARY_SIGNATURE_WAV_ENCODED);
+    }
 
-        if padding_length > 0 {
-            padded_data.resize(non_padded_size + padding_length, 0);
+    // unwrap alz4, l33t or sound encoding of entry data. None if data is not encoded
+    fn decode_entry(entry: &BarEntry, data: &[u8]) -> Result<Option<Vec<u8>>, ResourceError> {
+        if data.len() < 4 {
+            return Ok(None);
+        }
+        let signature = get_file_signature(data, 4);
+        // better to check signature, not is_encoded
+        let mut decoded_data: Option<Vec<u8>> = None;
+        // legacy archives have no is_encoded field, so l33t is detected by signature only
+        match entry.is_encoded {
+            2 => {
+                if signature == BINARY_SIGNATURE_WAV_ENCODED {
+                    decoded_data = Some(BarFile::decode_sound(data)?);
+                }
+            },
+            _ => {
+                match signature {
+                    BINARY_SIGNATURE_ALZ4 => {
+                        decoded_data = Some(alz4::decompress(data)?);
+                    },
+                    BINARY_SIGNATURE_L33T => {
+                        decoded_data = Some(l33t::decompress(data)?);
+                    },
+                    _ => (),
+                }
+            },
         }
-        let mut padded_data = &padded_data[..];
+        return Ok(decoded_data);
+    }
 
-        while padded_data.len() >= 8 {
-            let mut buf = padded_data[..8].try_into()?;         
-            let buf_value: u64 = u64::from_le_bytes(buf);    
-            
-            qword18 = (qword10.wrapping_mul(qword18.wrapping_add(qword8))).rotate_left(32);
-            buf = (buf_value ^ qword18).to_le_bytes();
+    // check that stored bytes of entry lie within archive
+    fn check_entry_range(entry: &BarEntry, file_size: u64) -> Result<(), ResourceError> {
+        if entry.offset.saturating_add(entry.file_size2 as u64) > file_size {
+            return Err(ResourceError::OffsetOutOfRange { offset: entry.offset, size: entry.file_size2 as u64, file_size: file_size });
+        }
+        return Ok(());
+    }
+
+    fn get_entry_index(&self, path: &str) -> Option<usize> {
+        let path = filter::normalize_entry_path(path);
+        return self.entries.iter().position(|entry| filter::normalize_entry_path(&entry.get_file_name_lossy()) == path);
+    }
+
+    // find entry by its path in archive. case-insensitive, accepts both / and \
+    pub fn get_entry(&self, path: &str) -> Option<&BarEntry> {
+        return self.get_entry_index(path).map(|index| &self.entries[index]);
+    }
 
-            decoded_data.extend_from_slice(&buf);
-            padded_data = &padded_data[8..];   
+    // size of data after unwrapping alz4 or l33t. encoded sound has size of original wav
+    fn get_uncompressed_size(data: &[u8]) -> u32 {
+        if data.len() >= 4 {
+            let size = match get_file_signature(data, 4) {
+                BINARY_SIGNATURE_ALZ4 => alz4::uncompressed_size(data).ok(),
+                BINARY_SIGNATURE_L33T => l33t::uncompressed_size(data).ok(),
+                _ => None,
+            };
+            if let Some(size) = size {
+                return size;
+            }
         }
+        return data.len() as u32;
+    }
 
-        decoded_data.resize(non_padded_size, 0);
-        let signature = decoded_data[..4].try_into()?;
-        let signature:u32 = u32::from_le_bytes(signature); 
-        assert_eq!(signature, BINARY_SIGNATURE_WAV_DECODED, "{}", ERR_NOT_VALID_DECODED_WAV_SIGNATURE);
-        return Ok(decoded_data);
+    // sizes, encoding type and write time of entry are taken from new data
+    fn set_entry_data(entry: &mut BarEntry, data: Vec<u8>) {
+        let signature = if data.len() >= 4 { get_file_signature(&data, 4) } else { 0 };
+        entry.is_encoded = match signature {
+            BINARY_SIGNATURE_ALZ4 | BINARY_SIGNATURE_L33T => 1,
+            BINARY_SIGNATURE_WAV_ENCODED => 2,
+            _ => 0,
+        };
+        entry.file_size1 = BarFile::get_uncompressed_size(&data);
+        entry.file_size2 = data.len() as u32;
+        entry.file_size3 = data.len() as u32;
+        entry.set_date_time(DateTime::from_system_time(SystemTime::now()));
+        entry.new_data = Some(data);
     }
 
-    /*pub fn multiextract(&self) -> Result<(), Box<dyn Error>> {
-        // multithreading extraction
-        let mut threads = vec![];  
-        let entries_chunked: Vec<Vec<BarEntry>> = self.entries.chunks(self.file_count as usize / 512).map(|x| x.to_vec()).collect();
-        
-        for chunk in entries_chunked{
-            threads.push(thread::spawn(move || {
-                for entry in chunk {    
-                    let file = File::open(PathBuf::from(&entry.bar_path)).expect(ERR_BAR_NOT_FOUND);
-                    let mut reader = BufReader::new(file);               
-
-                    reader.seek(SeekFrom::Start(entry.offset)).unwrap();
-                    let mut fdata = vec![0u8; entry.file_size2 as usize];
-                    reader.read_exact (&mut fdata).unwrap();
-                    let mut fpath = entry.get_path().unwrap();
-                    let prefix = fpath.parent().unwrap();
-                    fs::create_dir_all(prefix).unwrap();
-                    let signature = get_file_signature(&fdata, 4);
-                    // better to check signature, not is_encoded
-                    match entry.is_encoded {
-                        0 => {
-                            let mut writer = BufWriter::new(File::create(&fpath).unwrap());
-                            writer.write(&fdata).unwrap();
-                        },
-                        1 => {
-
-                        },
-                        2 => {
-                            
-                            if signature == BINARY_SIGNATURE_WAV_ENCODED {
-                                let decoded_data: Vec<u8> = BarFile::decode_sound(&fdata).unwrap();
-                                let mut writer = BufWriter::new(File::create(&fpath).unwrap());
-                                writer.write(&decoded_data).unwrap();
-                            }
-                        },
-                        _ => (),
-                    }   
-                    
-                    if signature == BINARY_SIGNATURE_DDT {
-                        fpath.set_extension("tga");
-                        let ddt_file = DdtFile::new(&fdata).unwrap();
-                        let tga_file = ddt_file.to_tga().unwrap();
-                        tga_file.save(fpath).unwrap();
+    // names which extraction would reject are not stored: relative parent, absolute, drive or empty parts
+    fn check_entry_name(path: &str) -> Result<(), ResourceError> {
+        if !path.split(['\\', '/']).all(is_safe_path_component) {
+            return Err(ResourceError::UnsafeEntryPath(path.to_owned()));
+        }
+        return Ok(());
+    }
+
+    // entry name is stored with backslashes, length in utf-16 chars
+    fn set_entry_name(entry: &mut BarEntry, path: &str) {
+        let chars: Vec<u16> = path.replace('/', "\\").encode_utf16().collect();
+        entry.file_name_length = chars.len() as u32;
+        entry.file_name = chars.iter().flat_map(|c| c.to_le_bytes()).collect();
+    }
+
+    // replace stored bytes of entry. data is written as is, compress it before if needed
+    pub fn replace_entry(&mut self, path: &str, data: Vec<u8>) -> Result<(), ResourceError> {
+        let index = self.get_entry_index(path).ok_or_else(|| ResourceError::EntryNotFound(path.to_owned()))?;
+        BarFile::set_entry_data(&mut self.entries[index], data);
+        self.modified = true;
+        return Ok(());
+    }
+
+    pub fn add_entry(&mut self, path: &str, data: Vec<u8>) -> Result<(), ResourceError> {
+        BarFile::check_entry_name(path)?;
+        if self.get_entry_index(path).is_some() {
+            return Err(ResourceError::EntryExists(path.to_owned()));
+        }
+        let mut entry = BarEntry {
+            offset: 0,
+            file_size1: 0,
+            file_size2: 0,
+            file_size3: 0,
+            year: 0,
+            month: 0,
+            day_of_week: 0,
+            day: 0,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            msecond: 0,
+            file_name_length: 0,
+            file_name: Vec::new(),
+            is_encoded: 0,
+            new_data: None,
+        };
+        BarFile::set_entry_name(&mut entry, path);
+        BarFile::set_entry_data(&mut entry, data);
+        self.entries.push(entry);
+        self.file_count = self.entries.len() as u32;
+        self.root_file_count = self.entries.len() as u32;
+        self.modified = true;
+        return Ok(());
+    }
+
+    pub fn delete_entry(&mut self, path: &str) -> Result<(), ResourceError> {
+        let index = self.get_entry_index(path).ok_or_else(|| ResourceError::EntryNotFound(path.to_owned()))?;
+        self.entries.remove(index);
+        self.file_count = self.entries.len() as u32;
+        self.root_file_count = self.entries.len() as u32;
+        self.modified = true;
+        return Ok(());
+    }
+
+    // rename or move entry to another directory inside archive
+    pub fn rename_entry(&mut self, path: &str, new_path: &str) -> Result<(), ResourceError> {
+        let index = self.get_entry_index(path).ok_or_else(|| ResourceError::EntryNotFound(path.to_owned()))?;
+        BarFile::check_entry_name(new_path)?;
+        if let Some(existing_index) = self.get_entry_index(new_path) {
+            if existing_index != index {
+                return Err(ResourceError::EntryExists(new_path.to_owned()));
+            }
+        }
+        BarFile::set_entry_name(&mut self.entries[index], new_path);
+        self.modified = true;
+        return Ok(());
+    }
+
+    // stored bytes of entry, as they are in archive
+    pub fn read_entry(&self, entry: &BarEntry) -> Result<Vec<u8>, ResourceError> {
+        if let Some(data) = &entry.new_data {
+            return Ok(data.clone());
+        }
+        let mut reader = self.reader.lock().unwrap_or_else(PoisonError::into_inner);
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        BarFile::check_entry_range(entry, file_size)?;
+        reader.seek(SeekFrom::Start(entry.offset))?;
+        let mut data = vec![0u8; entry.file_size2 as usize];
+        reader.read_exact(&mut data)?;
+        return Ok(data);
+    }
+
+    // bytes of entry with alz4, l33t or sound encoding unwrapped
+    pub fn read_decoded_entry(&self, entry: &BarEntry) -> Result<Vec<u8>, ResourceError> {
+        let data = self.read_entry(entry)?;
+        return Ok(BarFile::decode_entry(entry, &data)?.unwrap_or(data));
+    }
+
+    // streaming reader of stored bytes of entry, without loading whole entry to memory
+    pub fn entry_reader<'a>(&'a self, entry: &'a BarEntry) -> Result<EntryReader<'a, 'r>, ResourceError> {
+        if let Some(data) = &entry.new_data {
+            return Ok(EntryReader {
+                bar: self,
+                new_data: Some(data),
+                offset: 0,
+                size: data.len() as u64,
+                position: 0,
+            });
+        }
+        let file_size = self.reader.lock().unwrap_or_else(PoisonError::into_inner).seek(SeekFrom::End(0))?;
+        BarFile::check_entry_range(entry, file_size)?;
+        return Ok(EntryReader {
+            bar: self,
+            new_data: None,
+            offset: entry.offset,
+            size: entry.file_size2 as u64,
+            position: 0,
+        });
+    }
+
+    // check archive without writing anything: entries lie between header and files table, don't overlap,
+    // are not empty, have unique names and signatures matching is_encoded. full check also reads and decodes
+    // every entry. returns names of broken entries with errors
+    pub fn verify(&self, full: bool) -> Result<Vec<(String, String)>, ResourceError> {
+        let mut failed_entries: Vec<(String, String)> = Vec::new();
+        let header_size = self.header_to_bytes(0).len() as u64;
+        let mut names: HashSet<String> = HashSet::new();
+        let mut ranges: Vec<(u64, u64, String)> = Vec::new();
+        for entry in &self.entries {
+            let name = entry.get_file_name_lossy();
+            if !names.insert(filter::normalize_entry_path(&name)) {
+                failed_entries.push((name.clone(), ERR_DUPLICATE_ENTRY.to_owned()));
+            }
+            if entry.file_size2 == 0 {
+                failed_entries.push((name, ERR_EMPTY_ENTRY.to_owned()));
+                continue;
+            }
+            // data of added or replaced entries is not in file yet
+            if entry.new_data.is_none() {
+                let end = entry.offset.saturating_add(entry.file_size2 as u64);
+                if entry.offset < header_size || end > self.files_table_offset {
+                    failed_entries.push((name, format!("{} (0x{:X} + {}, 0x{:X}..0x{:X})", ERR_ENTRY_OUT_OF_DATA, entry.offset, entry.file_size2, header_size, self.files_table_offset)));
+                    continue;
+                }
+                ranges.push((entry.offset, end, name.clone()));
+            }
+
+            let result = (|| -> Result<Option<String>, ResourceError> {
+                let data = if full {
+                    self.read_entry(entry)?
+                }
+                else {
+                    let mut signature: Vec<u8> = Vec::new();
+                    self.entry_reader(entry)?.take(4).read_to_end(&mut signature)?;
+                    signature
+                };
+                let mut mismatch: Option<String> = None;
+                if self.version == BAR_VERSION_AOE3DE && data.len() >= 4 {
+                    let signature = get_file_signature(&data, 4);
+                    let is_compressed = signature == BINARY_SIGNATURE_ALZ4 || signature == BINARY_SIGNATURE_L33T;
+                    let is_sound = signature == BINARY_SIGNATURE_WAV_ENCODED;
+                    let matched = match entry.is_encoded {
+                        1 => is_compressed,
+                        2 => is_sound,
+                        _ => !is_compressed && !is_sound,
+                    };
+                    if !matched {
+                        mismatch = Some(format!("{} (0x{:08X}, {})", ERR_ENCODING_MISMATCH, signature, entry.is_encoded));
+                    }
+                }
+                if full {
+                    let decoded_data = BarFile::decode_entry(entry, &data)?;
+                    let data = decoded_data.as_ref().unwrap_or(&data);
+                    if data.len() >= 4 && get_file_signature(data, 4) == BINARY_SIGNATURE_DDT {
+                        DdtFile::read(data)?;
+                    }
+                    else if BarFile::is_xmb_entry(entry, data) {
+                        XmbFile::read(data)?;
                     }
                 }
-            }));
+                Ok(mismatch)
+            })();
+            match result {
+                Ok(Some(mismatch)) => failed_entries.push((name, mismatch)),
+                Ok(None) => (),
+                Err(error) => failed_entries.push((name, error.to_string())),
+            }
+        }
+
+        // entries sorted by offset overlap if one starts before end of any previous
+        ranges.sort_by_key(|range| range.0);
+        let mut last: Option<&(u64, u64, String)> = None;
+        for range in &ranges {
+            if let Some(previous) = last {
+                if range.0 < previous.1 {
+                    failed_entries.push((range.2.clone(), format!("{} {}", ERR_ENTRY_OVERLAP, previous.2)));
+                }
+            }
+            if last.is_none_or(|previous| range.1 > previous.1) {
+                last = Some(range);
+            }
+        }
+        return Ok(failed_entries);
+    }
+
+    // hashes of stored and decoded bytes of entries matching filter, in order of files table
+    pub fn hash_entries(&self, filter: &EntryFilter) -> Result<Vec<EntryHash>, ResourceError> {
+        let mut hashes: Vec<EntryHash> = Vec::new();
+        for entry in self.filter_entries(filter)? {
+            let result = (|| -> Result<EntryHash, ResourceError> {
+                let data = self.read_entry(entry)?;
+                let stored = ContentHash::new(&data);
+                let decoded = match BarFile::decode_entry(entry, &data)? {
+                    Some(decoded_data) => ContentHash::new(&decoded_data),
+                    None => stored,
+                };
+                Ok(EntryHash { name: entry.get_file_name()?, stored, decoded })
+            })();
+            hashes.push(result.map_err(|error| error.in_entry(entry.get_file_name_lossy(), entry.offset))?);
+        }
+        return Ok(hashes);
+    }
+
+    // entries which names match filter
+    pub fn filter_entries(&self, filter: &EntryFilter) -> Result<Vec<&BarEntry>, ResourceError> {
+        let mut entries: Vec<&BarEntry> = Vec::new();
+        for entry in &self.entries {
+            if filter.is_empty() {
+                entries.push(entry);
+                continue;
+            }
+            let file_name = entry.get_file_name().map_err(|error| error.in_entry(entry.get_file_name_lossy(), entry.offset))?;
+            if filter.matches(&file_name) {
+                entries.push(entry);
+            }
+        }
+        return Ok(entries);
+    }
+
+    pub fn get_root_path(&self) -> Result<String, ResourceError> {
+        return BarFile::vec_u8_to_string_u16(&self.root_path);
+    }
+
+    // path of extracted entry. names are checked unless archive is trusted
+    fn get_entry_path(&self, entry: &BarEntry, dest: &Path) -> Result<PathBuf, ResourceError> {
+        if self.trust_entry_paths {
+            return entry.get_trusted_path(dest, &self.root_path);
+        }
+        return entry.get_path(dest, &self.root_path);
+    }
+
+    // only .xmb entries are converted to xml
+    fn is_xmb_entry(entry: &BarEntry, data: &[u8]) -> bool {
+        return entry.get_file_name_lossy().to_lowercase().ends_with(".xmb") && xmb::is_xmb(data);
+    }
+
+    // extract one entry to extracted path, decode and convert it to converted path
+    fn extract_entry(&self, entry: &BarEntry, extracted_path: &Path, converted_path: &Path) -> Result<(), ResourceError> {
+        let extracted_entry_path = self.get_entry_path(entry, extracted_path)?;
+        let data = self.read_entry(entry)?;
+        let prefix = extracted_entry_path.parent().unwrap();
+        fs::create_dir_all(prefix)?;
+
+        let mut writer = BufWriter::new(File::create(&extracted_entry_path)?);
+        writer.write_all(&data)?;
+        writer.flush()?;
+        // de archives and old tools leave zero time, such files keep time of extraction
+        if let Some(modified_time) = entry.get_date_time().to_system_time() {
+            writer.get_ref().set_modified(modified_time)?;
+        }
+        drop(writer);
+        let decoded_data = BarFile::decode_entry(entry, &data)?;
+
+        if let Some(decoded_data) = &decoded_data {
+            let converted_entry_path = self.get_entry_path(entry, converted_path)?;
+            let prefix = converted_entry_path.parent().unwrap();
+            fs::create_dir_all(prefix)?;
+            let mut writer = BufWriter::new(File::create(&converted_entry_path)?);
+            writer.write_all(decoded_data)?;
         }
-        // run threads
-        for thread in threads{
-            thread.join();
+
+        let data = decoded_data.as_ref().unwrap_or(&data);
+        if data.len() >= 4 && get_file_signature(data, 4) == BINARY_SIGNATURE_DDT {
+            let mut converted_entry_path = self.get_entry_path(entry, converted_path)?;
+            let prefix = converted_entry_path.parent().unwrap();
+            fs::create_dir_all(prefix)?;
+            converted_entry_path.set_extension("tga");
+            let ddt_file = DdtFile::read(data)?;
+            let tga_file = ddt_file.to_tga()?;
+            tga_file.save(converted_entry_path)?;
+        }
+        else if BarFile::is_xmb_entry(entry, data) {
+            // raw entry is already extracted, broken xmb is reported by verify
+            if let Ok(xmb_file) = XmbFile::read(data) {
+                let converted_entry_path = self.get_entry_path(entry, converted_path)?;
+                let prefix = converted_entry_path.parent().unwrap();
+                fs::create_dir_all(prefix)?;
+                xmb_file.save_xml(XmbFile::xml_path(converted_entry_path))?;
+            }
         }
         return Ok(());
-    }*/
+    }
 
-    pub fn extract(&self) -> Result<(), Box<dyn Error>> {
-        let file = File::open(PathBuf::from(&self.bar_path)).expect(ERR_BAR_NOT_FOUND);
-        let mut reader = BufReader::new(file);    
+    // multithreading extraction. threads = 0 uses all available cores
+    pub fn multiextract(&self, managed_path: &Path, threads: usize, filter: &EntryFilter) -> Result<(), ResourceError> {
+        let threads = match threads {
+            0 => thread::available_parallelism().map_or(1, |count| count.get()),
+            _ => threads,
+        };
 
-        let managed_path = env::current_dir()?.join("managed").join("timing");
         let extracted_path = managed_path.join("extracted");
         let converted_path = managed_path.join("converted");
 
         self.to_csv(&extracted_path)?;
 
-        for entry in &self.entries {    
-           
+        // entries with same name go to one worker in table order, so last one wins as in sequential extraction
+        let mut groups: Vec<Vec<&BarEntry>> = Vec::new();
+        let mut group_indices: HashMap<String, usize> = HashMap::new();
+        for entry in self.filter_entries(filter)? {
+            let name = filter::normalize_entry_path(&entry.get_file_name_lossy());
+            match group_indices.get(&name) {
+                Some(&index) => groups[index].push(entry),
+                None => {
+                    group_indices.insert(name, groups.len());
+                    groups.push(vec![entry]);
+                }
+            }
+        }
 
-            reader.seek(SeekFrom::Start(entry.offset))?;
-            let mut data = vec![0u8; entry.file_size2 as usize];
-            reader.read_exact (&mut data)?;
-            let extracted_entry_path = entry.get_path(&extracted_path, &self.root_path)?;
-            let prefix = extracted_entry_path.parent().unwrap();
-            fs::create_dir_all(prefix)?;
-            let signature = get_file_signature(&data, 4);
+        // workers take entries one by one, so a few big textures don't stall whole chunk.
+        // reading is serialized by archive reader, decoding and converting run in parallel
+        let next_group = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results: Vec<Result<(), ResourceError>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads).map(|_| {
+                scope.spawn(|| -> Result<(), ResourceError> {
+                    while !failed.load(Ordering::Relaxed) {
+                        let group = match groups.get(next_group.fetch_add(1, Ordering::Relaxed)) {
+                            Some(group) => group,
+                            None => break,
+                        };
+                        for entry in group {
+                            if let Err(error) = self.extract_entry(entry, &extracted_path, &converted_path) {
+                                failed.store(true, Ordering::Relaxed);
+                                return Err(error.in_entry(entry.get_file_name_lossy(), entry.offset));
+                            }
+                        }
+                    }
+                    Ok(())
+                })
+            }).collect();
+            workers.into_iter().map(|worker| match worker.join() {
+                Ok(result) => result,
+                Err(panic) => panic::resume_unwind(panic),
+            }).collect()
+        });
 
-            let mut writer = BufWriter::new(File::create(&extracted_entry_path)?);
-            writer.write(&data)?;
-            // better to check signature, not is_encoded
-            match entry.is_encoded {
-                1 => {
+        for result in results {
+            result?;
+        }
+        return Ok(());
+    }
 
-                },
-                2 => {
-                    if signature == BINARY_SIGNATURE_WAV_ENCODED {
-                        let converted_entry_path = entry.get_path(&converted_path, &self.root_path)?;
-                        let prefix = converted_entry_path.parent().unwrap();
-                        fs::create_dir_all(prefix)?;
-                        let decoded_data: Vec<u8> = BarFile::decode_sound(&data)?;
-                        let mut writer = BufWriter::new(File::create(&converted_entry_path)?);
-                        writer.write(&decoded_data)?;
-                    }
-                },
-                _ => (),
-            }   
-            
-            if signature == BINARY_SIGNATURE_DDT {
-                let mut converted_entry_path = entry.get_path(&converted_path, &self.root_path)?;
-                let prefix = converted_entry_path.parent().unwrap();
-                fs::create_dir_all(prefix)?;
-                converted_entry_path.set_extension("tga");
-                let ddt_file = DdtFile::read(&data)?;
-                let tga_file = ddt_file.to_tga()?;
-                tga_file.save(converted_entry_path)?;
+    // extract to managed_path/extracted, decoded and converted entries go to managed_path/converted
+    pub fn extract(&self, managed_path: &Path, filter: &EntryFilter) -> Result<(), ResourceError> {
+        let extracted_path = managed_path.join("extracted");
+        let converted_path = managed_path.join("converted");
+
+        self.to_csv(&extracted_path)?;
+
+        for entry in self.filter_entries(filter)? {    
+            self.extract_entry(entry, &extracted_path, &converted_path)
+                .map_err(|error| error.in_entry(entry.get_file_name_lossy(), entry.offset))?;
+        }
+        return Ok(());
+    }
+
+    // header with every unknown field kept as it was read
+    fn header_to_bytes(&self, files_table_offset: u64) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&self.signature.to_le_bytes());
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.magic.to_le_bytes());
+        bytes.extend_from_slice(&self.unk1);
+        bytes.extend_from_slice(&self.unk2.to_le_bytes());
+        bytes.extend_from_slice(&self.file_count.to_le_bytes());
+        if self.version == BAR_VERSION_AOE3 {
+            bytes.extend_from_slice(&(files_table_offset as u32).to_le_bytes());
+        }
+        else {
+            bytes.extend_from_slice(&self.unk3.to_le_bytes());
+            bytes.extend_from_slice(&files_table_offset.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.unk4.to_le_bytes());
+        if self.version == BAR_VERSION_AOE3DE {
+            bytes.extend_from_slice(&self.unk5.to_le_bytes());
+        }
+        return bytes;
+    }
+
+    fn table_to_bytes(&self, entries: &[BarEntry]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&self.root_path_length.to_le_bytes());
+        bytes.extend_from_slice(&self.root_path);
+        bytes.extend_from_slice(&self.root_file_count.to_le_bytes());
+        for entry in entries {
+            bytes.extend_from_slice(&entry.to_bytes(self.version));
+        }
+        return bytes;
+    }
+
+    // copy start..end bytes of source archive to writer
+    fn copy_source(&self, writer: &mut dyn Write, start: u64, end: u64) -> Result<(), ResourceError> {
+        if end <= start {
+            return Ok(());
+        }
+        let mut reader = self.reader.lock().unwrap_or_else(PoisonError::into_inner);
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        if end > file_size {
+            return Err(ResourceError::OffsetOutOfRange { offset: start, size: end - start, file_size: file_size });
+        }
+        reader.seek(SeekFrom::Start(start))?;
+        io::copy(&mut (&mut **reader).take(end - start), writer)?;
+        return Ok(());
+    }
+
+    // write archive from this struct. header and files table are serialized from fields,
+    // data area and bytes after files table are copied from source, so open -> write gives identical file.
+    // after entries were changed, data of every entry is written one after another and offsets are updated
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), ResourceError> {
+        if self.modified {
+            return self.write_rebuilt(writer);
+        }
+        let header = self.header_to_bytes(self.files_table_offset);
+        let table = self.table_to_bytes(&self.entries);
+        writer.write_all(&header)?;
+        self.copy_source(writer, header.len() as u64, self.files_table_offset)?;
+        writer.write_all(&table)?;
+        let file_size = self.reader.lock().unwrap_or_else(PoisonError::into_inner).seek(SeekFrom::End(0))?;
+        self.copy_source(writer, self.files_table_offset + table.len() as u64, file_size)?;
+        return Ok(());
+    }
+
+    // legacy archives store offsets in u32. files table is after all entries, so checking its offset is enough
+    fn check_offset(version: u32, offset: u64) -> Result<(), ResourceError> {
+        if version == BAR_VERSION_AOE3 && offset > u32::MAX as u64 {
+            return Err(ResourceError::OffsetTooLarge(offset));
+        }
+        return Ok(());
+    }
+
+    fn write_rebuilt<W: Write>(&self, writer: &mut W) -> Result<(), ResourceError> {
+        let header_size = self.header_to_bytes(0).len() as u64;
+        let mut entries: Vec<BarEntry> = Vec::with_capacity(self.entries.len());
+        let mut offset = header_size;
+        for entry in &self.entries {
+            let mut written_entry = entry.clone();
+            written_entry.new_data = None;
+            written_entry.offset = offset;
+            offset += entry.file_size2 as u64;
+            entries.push(written_entry);
+        }
+        BarFile::check_offset(self.version, offset)?;
+        writer.write_all(&self.header_to_bytes(offset))?;
+        for entry in &self.entries {
+            match &entry.new_data {
+                Some(data) => writer.write_all(data)?,
+                None => self.copy_source(writer, entry.offset, entry.offset + entry.file_size2 as u64)
+                    .map_err(|error| error.in_entry(entry.get_file_name_lossy(), entry.offset))?,
+            }
+        }
+        writer.write_all(&self.table_to_bytes(&entries))?;
+        return Ok(());
+    }
+
+    // write archive over opened file: new archive goes to temporary file which then replaces source
+    pub fn rewrite(&mut self) -> Result<(), ResourceError> {
+        if self.bar_path.as_os_str().is_empty() {
+            return Err(ResourceError::InvalidPath(self.bar_path.clone()));
+        }
+        let mut temp_path = self.bar_path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+
+        // source file must be closed before it can be replaced on windows
+        self.reader = Mutex::new(Box::new(io::Cursor::new(Vec::new())));
+        if let Err(error) = fs::rename(&temp_path, &self.bar_path) {
+            self.reader = Mutex::new(Box::new(BufReader::new(File::open(&self.bar_path)?)));
+            fs::remove_file(&temp_path)?;
+            return Err(error.into());
+        }
+        *self = BarFile::open(self.bar_path.clone())?;
+        return Ok(());
+    }
+
+    // append added and replaced entries to the end of opened file and write new files table after them.
+    // data of deleted or replaced entries and old files table stay in file as dead space until compact.
+    // header is updated last, so interrupted append leaves archive with old files table
+    pub fn append(&mut self) -> Result<(), ResourceError> {
+        if self.bar_path.as_os_str().is_empty() {
+            return Err(ResourceError::InvalidPath(self.bar_path.clone()));
+        }
+        if !self.modified {
+            return Ok(());
+        }
+        let mut file = fs::OpenOptions::new().write(true).open(&self.bar_path)?;
+        let mut offset = file.seek(SeekFrom::End(0))?;
+        let appended_size: u64 = self.entries.iter().filter_map(|entry| entry.new_data.as_ref()).map(|data| data.len() as u64).sum();
+        BarFile::check_offset(self.version, offset + appended_size)?;
+        let mut writer = BufWriter::new(file);
+        let mut entries: Vec<BarEntry> = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            let mut written_entry = entry.clone();
+            if let Some(data) = &entry.new_data {
+                writer.write_all(data)?;
+                written_entry.offset = offset;
+                written_entry.new_data = None;
+                offset += data.len() as u64;
             }
+            entries.push(written_entry);
         }
-        Command::new("explorer")
-            .arg(managed_path)
-            .spawn()
-            .unwrap();
+        writer.write_all(&self.table_to_bytes(&entries))?;
+        let mut file = writer.into_inner().map_err(|error| error.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&self.header_to_bytes(offset))?;
+        file.flush()?;
+        drop(file);
+
+        *self = BarFile::open(self.bar_path.clone())?;
         return Ok(());
     }
 
-    fn to_csv(&self, dest: &PathBuf) -> Result<(), Box<dyn Error>>{
+    // rewrite opened file keeping only data referenced by files table. returns number of reclaimed bytes
+    pub fn compact(&mut self) -> Result<u64, ResourceError> {
+        if self.bar_path.as_os_str().is_empty() {
+            return Err(ResourceError::InvalidPath(self.bar_path.clone()));
+        }
+        let old_size = fs::metadata(&self.bar_path)?.len();
+        self.modified = true;
+        self.rewrite()?;
+        let new_size = fs::metadata(&self.bar_path)?.len();
+        return Ok(old_size.saturating_sub(new_size));
+    }
+
+    // write archive to another file. source archive is still being read, so path must differ from bar_path
+    pub fn save(&self, path: PathBuf) -> Result<(), ResourceError> {
+        if !self.bar_path.as_os_str().is_empty() && path.exists() && fs::canonicalize(&path)? == fs::canonicalize(&self.bar_path)? {
+            return Err(ResourceError::InvalidPath(path));
+        }
+        let mut writer = BufWriter::new(File::create(&path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        return Ok(());
+    }
+
+    fn to_csv(&self, dest: &PathBuf) -> Result<(), ResourceError>{
         fs::create_dir_all(dest)?;
         let path = PathBuf::from(dest).join("__entries.csv");
         let mut file = BufWriter::new(File::create(path)?);
@@ -294,28 +983,52 @@ impl BarFile{
     }
 
    
-    pub fn open(path: PathBuf) -> Result<BarFile, Box<dyn Error>> {
+    pub fn open(path: PathBuf) -> Result<BarFile<'r>, ResourceError> {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Err(ResourceError::BarNotFound(path)),
+            Err(error) => return Err(error.into()),
+        };
+        let mut bar = BarFile::read(file)?;
+        bar.bar_path = path;
+        return Ok(bar);
+    }
+
+    // parse archive from any seekable source, also entry_reader of outer archive. reader is kept to read entries later
+    pub fn read<R: Read + Seek + Send + 'r>(reader: R) -> Result<BarFile<'r>, ResourceError> {
         let from_dump: bool = false;
-        let file = File::open(&path).expect(&ERR_BAR_NOT_FOUND);
-        let mut reader = BufReader::new(file);
+        let mut reader = BufReader::new(reader);
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        if file_size < 8 {
+            return Err(ResourceError::TruncatedHeader(FileFormat::Bar));
+        }
 
         let mut signature = [0u8; 4];
         reader.read_exact (&mut signature)?;
         let signature: u32 = u32::from_le_bytes(signature);
-        assert_eq!(signature, BINARY_SIGNATURE_BAR, "{}", ERR_NOT_VALID_BAR_SIGNATURE);
+        if signature != BINARY_SIGNATURE_BAR {
+            return Err(ResourceError::BadSignature { format: FileFormat::Bar, signature: signature });
+        }
 
         let mut version = [0u8; 4];
         reader.read_exact (&mut version)?;
         let version: u32 = u32::from_le_bytes(version);
 
         if version != BAR_VERSION_AOE3DE && version != BAR_VERSION_AOE3 {
-            panic!("{}", ERR_NOT_SUPPORTED_BAR_VERSION);
+            return Err(ResourceError::UnsupportedVersion(version));
+        }
+        let header_size = if version == BAR_VERSION_AOE3DE { BAR_HEADER_SIZE_AOE3DE } else { BAR_HEADER_SIZE_AOE3 };
+        if file_size < header_size {
+            return Err(ResourceError::TruncatedHeader(FileFormat::Bar));
         }
 
         let mut magic = [0u8; 4];
         reader.read_exact (&mut magic)?;
         let magic: u32 = u32::from_le_bytes(magic);
-        assert_eq!(magic, BINARY_BAR_MAGIC, "{}", ERR_NOT_VALID_BAR_MAGIC);
+        if magic != BINARY_BAR_MAGIC {
+            return Err(ResourceError::BadMagic(magic));
+        }
 
         let mut unk1 = [0u8; 264];
         reader.read_exact (&mut unk1)?;
@@ -355,129 +1068,148 @@ impl BarFile{
             unk5 = u32::from_le_bytes(_unk5);                
         }
 
+        // files table starts with root path length and files count
+        if files_table_offset.saturating_add(8) > file_size {
+            return Err(ResourceError::OffsetOutOfRange { offset: files_table_offset, size: 8, file_size: file_size });
+        }
         if from_dump == false{
             reader.seek(SeekFrom::Start(files_table_offset))?;
         }
         let mut root_path_length = [0u8; 4];
         reader.read_exact (&mut root_path_length)?;
         let root_path_length: u32 = u32::from_le_bytes(root_path_length);
+        if root_path_length as u64 * 2 > file_size {
+            return Err(ResourceError::TruncatedTable { offset: files_table_offset, index: 0 });
+        }
         let mut root_path = vec![0u8; root_path_length as usize * 2];
-        reader.read_exact (&mut root_path)?;
+        reader.read_exact (&mut root_path).map_err(|error| table_error(error, files_table_offset, 0))?;
         let mut root_file_count = [0u8; 4];
-        reader.read_exact (&mut root_file_count)?;
+        reader.read_exact (&mut root_file_count).map_err(|error| table_error(error, files_table_offset, 0))?;
         let root_file_count: u32 = u32::from_le_bytes(root_file_count);
 
-        assert_eq!(file_count, root_file_count, "{}", ERR_NOT_MATCHED_ENTRY_COUNT);
+        if file_count != root_file_count {
+            return Err(ResourceError::EntryCountMismatch { file_count: file_count, root_file_count: root_file_count });
+        }
 
         let mut entries: Vec<BarEntry> = Vec::new();
-        for _ in 0..root_file_count {
-
-            let offset: u64;
-            if version == BAR_VERSION_AOE3 {
-                let mut _offset = [0u8; 4];
-                reader.read_exact (&mut _offset)?;
-                offset = u32::from_le_bytes(_offset) as u64;
-            }
-            else {
-                let mut _offset = [0u8; 8];
-                reader.read_exact (&mut _offset)?;
-                offset = u64::from_le_bytes(_offset);
-            }
-            let mut fsize1 = [0u8; 4];
-            reader.read_exact (&mut fsize1)?;
-            let fsize1: u32 = u32::from_le_bytes(fsize1);
-
-            let mut fsize2 = [0u8; 4];
-            reader.read_exact (&mut fsize2)?;
-            let fsize2: u32 = u32::from_le_bytes(fsize2);
-            
-            let mut fsize3: u32 = 0;
-
-            let mut year: u16 = 0;
-            let mut month: u16 = 0;  
-            let mut day_of_week: u16 = 0;
-            let mut day: u16 = 0; 
-            let mut hour: u16 = 0;
-            let mut minute: u16 = 0;
-            let mut second: u16 = 0;  
-            let mut msecond: u16 = 0;
+        let result = (|| -> io::Result<()> {
+            for _ in 0..root_file_count {
+
+                let offset: u64;
+                if version == BAR_VERSION_AOE3 {
+                    let mut _offset = [0u8; 4];
+                    reader.read_exact (&mut _offset)?;
+                    offset = u32::from_le_bytes(_offset) as u64;
+                }
+                else {
+                    let mut _offset = [0u8; 8];
+                    reader.read_exact (&mut _offset)?;
+                    offset = u64::from_le_bytes(_offset);
+                }
+                let mut fsize1 = [0u8; 4];
+                reader.read_exact (&mut fsize1)?;
+                let fsize1: u32 = u32::from_le_bytes(fsize1);
 
-            if version == BAR_VERSION_AOE3DE {
-                let mut _fsize3 = [0u8; 4];
-                reader.read_exact (&mut _fsize3)?;
-                fsize3 = u32::from_le_bytes(_fsize3); 
-            }
-            else {
-                let mut _year = [0u8; 2];
-                reader.read_exact (&mut _year)?;
-                year = u16::from_le_bytes(_year); 
+                let mut fsize2 = [0u8; 4];
+                reader.read_exact (&mut fsize2)?;
+                let fsize2: u32 = u32::from_le_bytes(fsize2);
                 
-                let mut _month = [0u8; 2];
-                reader.read_exact (&mut _month)?;
-                month = u16::from_le_bytes(_month); 
+                let mut fsize3: u32 = 0;
+
+                let mut year: u16 = 0;
+                let mut month: u16 = 0;  
+                let mut day_of_week: u16 = 0;
+                let mut day: u16 = 0; 
+                let mut hour: u16 = 0;
+                let mut minute: u16 = 0;
+                let mut second: u16 = 0;  
+                let mut msecond: u16 = 0;
+
+                if version == BAR_VERSION_AOE3DE {
+                    let mut _fsize3 = [0u8; 4];
+                    reader.read_exact (&mut _fsize3)?;
+                    fsize3 = u32::from_le_bytes(_fsize3); 
+                }
+                else {
+                    let mut _year = [0u8; 2];
+                    reader.read_exact (&mut _year)?;
+                    year = u16::from_le_bytes(_year); 
+                    
+                    let mut _month = [0u8; 2];
+                    reader.read_exact (&mut _month)?;
+                    month = u16::from_le_bytes(_month); 
 
-                let mut _day_of_week = [0u8; 2];
-                reader.read_exact (&mut _day_of_week)?;
-                day_of_week = u16::from_le_bytes(_day_of_week); 
+                    let mut _day_of_week = [0u8; 2];
+                    reader.read_exact (&mut _day_of_week)?;
+                    day_of_week = u16::from_le_bytes(_day_of_week); 
 
-                let mut _day = [0u8; 2];
-                reader.read_exact (&mut _day)?;
-                day = u16::from_le_bytes(_day); 
+                    let mut _day = [0u8; 2];
+                    reader.read_exact (&mut _day)?;
+                    day = u16::from_le_bytes(_day); 
 
-                let mut _hour = [0u8; 2];
-                reader.read_exact (&mut _hour)?;
-                hour = u16::from_le_bytes(_hour); 
+                    let mut _hour = [0u8; 2];
+                    reader.read_exact (&mut _hour)?;
+                    hour = u16::from_le_bytes(_hour); 
 
-                let mut _minute = [0u8; 2];
-                reader.read_exact (&mut _minute)?;
-                minute = u16::from_le_bytes(_minute); 
+                    let mut _minute = [0u8; 2];
+                    reader.read_exact (&mut _minute)?;
+                    minute = u16::from_le_bytes(_minute); 
 
-                let mut _second = [0u8; 2];
-                reader.read_exact (&mut _second)?;
-                second = u16::from_le_bytes(_second); 
+                    let mut _second = [0u8; 2];
+                    reader.read_exact (&mut _second)?;
+                    second = u16::from_le_bytes(_second); 
 
-                let mut _msecond = [0u8; 2];
-                reader.read_exact (&mut _msecond)?;
-                msecond = u16::from_le_bytes(_msecond); 
-            }
-            
-            let mut flength = [0u8; 4];
-            reader.read_exact (&mut flength)?;
-            let flength: u32 = u32::from_le_bytes(flength); 
-            
-            let mut fname = vec![0u8; (flength * 2).try_into()?];
-            reader.read_exact (&mut fname)?;
-            let mut is_encoded: u32 = 0;
-            if version == BAR_VERSION_AOE3DE {
-                let mut _is_encoded = [0u8; 4];
-                reader.read_exact (&mut _is_encoded)?;
-                is_encoded = u32::from_le_bytes(_is_encoded);   
+                    let mut _msecond = [0u8; 2];
+                    reader.read_exact (&mut _msecond)?;
+                    msecond = u16::from_le_bytes(_msecond); 
+                }
+                
+                let mut flength = [0u8; 4];
+                reader.read_exact (&mut flength)?;
+                let flength: u32 = u32::from_le_bytes(flength); 
+                
+                // don't allocate more than file can hold
+                if flength as u64 * 2 > file_size {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                let mut fname = vec![0u8; flength as usize * 2];
+                reader.read_exact (&mut fname)?;
+                let mut is_encoded: u32 = 0;
+                if version == BAR_VERSION_AOE3DE {
+                    let mut _is_encoded = [0u8; 4];
+                    reader.read_exact (&mut _is_encoded)?;
+                    is_encoded = u32::from_le_bytes(_is_encoded);   
+                }
+                let entry = BarEntry {
+                    //bar_path: path, 
+                    //: root_path.clone(),
+                    offset: offset, 
+                    file_size1: fsize1, 
+                    file_size2: fsize2, 
+                    file_size3: fsize3, 
+                    year: year, 
+                    month: month, 
+                    day_of_week: day_of_week, 
+                    day: day, 
+                    hour: hour, 
+                    minute: minute, 
+                    second: second, 
+                    msecond: msecond,
+                    file_name_length: flength, 
+                    file_name: fname, 
+                    is_encoded: is_encoded,
+                    new_data: None,
+                };
+                
+                entries.push(entry);
             }
-            let entry = BarEntry {
-                //bar_path: path, 
-                //: root_path.clone(),
-                offset: offset, 
-                file_size1: fsize1, 
-                file_size2: fsize2, 
-                file_size3: fsize3, 
-                year: year, 
-                month: month, 
-                day_of_week: day_of_week, 
-                day: day, 
-                hour: hour, 
-                minute: minute, 
-                second: second, 
-                msecond: msecond,
-                file_name_length: flength, 
-                file_name: fname, 
-                is_encoded: is_encoded
-            };
-            
-            entries.push(entry);
-        }
+            Ok(())
+        })();
+        result.map_err(|error| table_error(error, files_table_offset, entries.len()))?;
 
         return Ok(BarFile { 
-            bar_path: path,         
+            bar_path: PathBuf::new(),
+            reader: Mutex::new(Box::new(reader)),
             signature: signature,
             version: version,
             magic: magic,
@@ -492,25 +1224,23 @@ impl BarFile{
             root_path: root_path,
             root_file_count: root_file_count,
             entries: entries,   
+            modified: false,
+            trust_entry_paths: false,
         });
     }
 
-    pub fn create(dir: PathBuf, version: u32) -> Result<BarFile, Box<dyn Error>> {
+    // compress: glob patterns or extensions of entries to compress with alz4 (DE) or l33t (legacy)
+    pub fn create(dir: PathBuf, managed_path: &Path, version: u32, compress: &[String]) -> Result<BarFile<'r>, ResourceError> {
 
-        let managed_path = env::current_dir()?.join("managed").join("timing");
         let created_path = managed_path.join("created");
         fs::create_dir_all(&created_path)?;
-        let mut bar_path = created_path.join(dir.file_name().unwrap());
+        let dir_name = dir.file_name().ok_or_else(|| ResourceError::InvalidPath(dir.clone()))?.to_owned();
+        let mut bar_path = created_path.join(&dir_name);
         bar_path.set_extension("bar");
 
         let files = get_raw_bar_entries_in_directory(&dir)?;
         let files_count: u32 = files.len() as u32;
 
-        let mut size: u64 = 0;
-        for file in &files {
-            size += file.size;
-        }
-
         let file = File::create(&bar_path)?;
         let mut writer = BufWriter::new(file);
 
@@ -520,15 +1250,16 @@ impl BarFile{
         writer.write_all(&[0u8; 264])?;
         writer.write_all(&[0u8; 4])?;
         writer.write_all(&files_count.to_le_bytes())?;
-        let files_table_offset: u64;
+        // files table offset is known only after writing data
         if version == BAR_VERSION_AOE3DE {
             writer.write_all(&[0u8; 4])?;
-            files_table_offset = size + 304;
-            writer.write_all(&files_table_offset.to_le_bytes())?;
+        }
+        let files_table_offset_position: u64 = writer.stream_position()?;
+        if version == BAR_VERSION_AOE3DE {
+            writer.write_all(&[0u8; 8])?;
         }
         else {
-            files_table_offset = size + 292;
-            writer.write_all(&(files_table_offset as u32).to_le_bytes())?;
+            writer.write_all(&[0u8; 4])?;
         }
         writer.write_all(&[0u8; 4])?;
 
@@ -536,18 +1267,36 @@ impl BarFile{
             writer.write_all(&[0u8; 4])?;
         }
 
-        let start_offset: u64 = writer.seek(SeekFrom::Current(0))?;
+        let start_offset: u64 = writer.stream_position()?;
         let mut is_encoded_vec: Vec<u32> = Vec::new();
+        let mut file_size1_vec: Vec<u32> = Vec::new();
+        let mut file_size2_vec: Vec<u32> = Vec::new();
         for f in &files {
             
             let file = File::open(&f.path)?;
             let mut reader = BufReader::new(file);
             
-            let mut data: Vec<u8> = Vec::new();
+            let mut data: Vec<u8> = Vec::with_capacity(f.size as usize);
             reader.read_to_end(&mut data)?;
 
-            let is_encoded = data[..4].try_into()?;
-            let is_encoded: u32 = u32::from_le_bytes(is_encoded); 
+            let relative_file_path: String = get_entry_name(&f.path, &dir)?;
+            // de game reads only encrypted sounds
+            if version == BAR_VERSION_AOE3DE && sound::is_wav(&data) {
+                data = BarFile::encode_sound(&data)?;
+            }
+            if filter::glob_match_any(compress, &relative_file_path) && !BarFile::is_encoded_data(&data) {
+                if version == BAR_VERSION_AOE3DE {
+                    data = alz4::compress(&data);
+                }
+                else {
+                    data = l33t::compress(&data)?;
+                }
+            }
+            // game allocates uncompressed size, for already compressed files it is taken from their header
+            file_size1_vec.push(BarFile::get_uncompressed_size(&data));
+            file_size2_vec.push(data.len() as u32);
+
+            let is_encoded: u32 = if data.len() >= 4 { get_file_signature(&data, 4) } else { 0 };
             match is_encoded {
                 BINARY_SIGNATURE_ALZ4 => {
                     is_encoded_vec.push(1);
@@ -565,14 +1314,27 @@ impl BarFile{
             
             writer.write_all(&data)?;
         }
-        let root_path = dir.file_name().unwrap().to_str().unwrap().to_owned() + "\\";
+
+        let files_table_offset: u64 = writer.stream_position()?;
+        BarFile::check_offset(version, files_table_offset)?;
+        writer.seek(SeekFrom::Start(files_table_offset_position))?;
+        if version == BAR_VERSION_AOE3DE {
+            writer.write_all(&files_table_offset.to_le_bytes())?;
+        }
+        else {
+            writer.write_all(&(files_table_offset as u32).to_le_bytes())?;
+        }
+        writer.seek(SeekFrom::Start(files_table_offset))?;
+
+        let root_path = dir_name.to_string_lossy().into_owned() + "\\";
 
 
 
-        let root_path_len: u32 = root_path.len() as u32;
+        let root_path_vec_16: Vec<u16> = root_path.encode_utf16().collect();
+        // lengths are counted in utf-16 chars, not bytes
+        let root_path_len: u32 = root_path_vec_16.len() as u32;
         
         writer.write_all(&root_path_len.to_le_bytes())?;
-        let root_path_vec_16: Vec<u16> = root_path.encode_utf16().collect();
         let mut root_path_vec_8: Vec<u8> = Vec::new();
         for u16_byte in root_path_vec_16 {
             root_path_vec_8.append(&mut u16_byte.to_le_bytes().to_vec());         
@@ -585,42 +1347,32 @@ impl BarFile{
 
         let mut entries: Vec<BarEntry> = Vec::new();
         for (i, file) in files.iter().enumerate() {
-            let file_size = file.size as u32;
+            let file_size = file_size2_vec[i];
+            let date_time = if version == BAR_VERSION_AOE3 { DateTime::from_system_time(file.modified_datetime) } else { DateTime::default() };
             if version == BAR_VERSION_AOE3 {
                 writer.write_all(&(offset as u32).to_le_bytes())?;
+                writer.write_all(&file_size1_vec[i].to_le_bytes())?;
                 writer.write_all(&file_size.to_le_bytes())?;
-                writer.write_all(&file_size.to_le_bytes())?;
-                
-
-                // NEED TO KNOW DATA TIME
-                writer.write_all(&[0u8; 2])?;
-                writer.write_all(&[0u8; 2])?;
-                writer.write_all(&[0u8; 2])?;
-                writer.write_all(&[0u8; 2])?;
-                writer.write_all(&[0u8; 2])?;
-                writer.write_all(&[0u8; 2])?;
-                writer.write_all(&[0u8; 2])?;
-                writer.write_all(&[0u8; 2])?;
+                writer.write_all(&date_time.year.to_le_bytes())?;
+                writer.write_all(&date_time.month.to_le_bytes())?;
+                writer.write_all(&date_time.day_of_week.to_le_bytes())?;
+                writer.write_all(&date_time.day.to_le_bytes())?;
+                writer.write_all(&date_time.hour.to_le_bytes())?;
+                writer.write_all(&date_time.minute.to_le_bytes())?;
+                writer.write_all(&date_time.second.to_le_bytes())?;
+                writer.write_all(&date_time.msecond.to_le_bytes())?;
             }
             else{
                 writer.write_all(&offset.to_le_bytes())?;
-                //check if alz4 or l33t (and maybe sound?) to determine uncomprerssed file size
-                if is_encoded_vec[i] == 1 {
-
-                }
-                else{
-
-                }
-    
-                writer.write_all(&[0u8; 4])?;
+                writer.write_all(&file_size1_vec[i].to_le_bytes())?;
 
                 writer.write_all(&file_size.to_le_bytes())?;
                 writer.write_all(&file_size.to_le_bytes())?;
             }
-            let relative_file_path: String = file.path.strip_prefix(&dir)?.display().to_string();
-            let relative_file_path_len: u32 = relative_file_path.len() as u32;
-            writer.write_all(&relative_file_path_len.to_le_bytes())?;
+            let relative_file_path: String = get_entry_name(&file.path, &dir)?;
             let relative_file_path_vec_16: Vec<u16> = relative_file_path.encode_utf16().collect();
+            let relative_file_path_len: u32 = relative_file_path_vec_16.len() as u32;
+            writer.write_all(&relative_file_path_len.to_le_bytes())?;
             let mut relative_file_path_vec_8: Vec<u8> = Vec::new();
             for u16_byte in relative_file_path_vec_16 {
                 relative_file_path_vec_8.append(&mut u16_byte.to_le_bytes().to_vec());             
@@ -636,20 +1388,21 @@ impl BarFile{
                 //bar_path: bar_path,   
                 //root_path: root_path_vec_8.clone(),
                 offset: offset, 
-                file_size1: 0, 
+                file_size1: file_size1_vec[i], 
                 file_size2: file_size, 
                 file_size3: file_size, 
-                year: 0, 
-                month: 0, 
-                day_of_week: 0, 
-                day: 0, 
-                hour: 0, 
-                minute: 0, 
-                second: 0, 
-                msecond: 0,
+                year: date_time.year, 
+                month: date_time.month, 
+                day_of_week: date_time.day_of_week, 
+                day: date_time.day, 
+                hour: date_time.hour, 
+                minute: date_time.minute, 
+                second: date_time.second, 
+                msecond: date_time.msecond,
                 file_name_length: relative_file_path_len, 
                 file_name: relative_file_path_vec_8, 
-                is_encoded: is_encoded_vec[i]
+                is_encoded: is_encoded_vec[i],
+                new_data: None,
             };
             
             entries.push(entry);
@@ -658,7 +1411,11 @@ impl BarFile{
 
         }
 
+        writer.flush()?;
+        drop(writer);
+
         let bar = BarFile { 
+            reader: Mutex::new(Box::new(BufReader::new(File::open(&bar_path)?))),
             bar_path: bar_path,         
             signature: BINARY_SIGNATURE_BAR,
             version: version,
@@ -674,6 +1431,8 @@ impl BarFile{
             root_path: root_path_vec_8,
             root_file_count: files_count,
             entries: entries,   
+            modified: false,
+            trust_entry_paths: false,
         };
 
         bar.to_csv(&created_path)?;
@@ -694,13 +1453,13 @@ const TEST_DIR_LEGACY_PATH: &str = "C:\\Users\\NOKOMPL\\Desktop\\ResourceManager
 #[test]
 #[ignore]
 fn create_de_bar_file(){
-    BarFile::create(PathBuf::from(TEST_DIR_DE_PATH), BAR_VERSION_AOE3DE).unwrap();          
+    BarFile::create(PathBuf::from(TEST_DIR_DE_PATH), &std::env::current_dir().unwrap().join("managed"), BAR_VERSION_AOE3DE, &[]).unwrap();          
 }
 
 #[test]
 #[ignore]
 fn create_legacy_bar_file(){
-    BarFile::create(PathBuf::from(TEST_DIR_LEGACY_PATH), BAR_VERSION_AOE3).unwrap();          
+    BarFile::create(PathBuf::from(TEST_DIR_LEGACY_PATH), &std::env::current_dir().unwrap().join("managed"), BAR_VERSION_AOE3, &[]).unwrap();          
 }
 
 #[test]
@@ -729,12 +1488,521 @@ fn decode_sound_file(){
 #[ignore]
 fn extract_de_bar_file(){
     let bar = BarFile::open(PathBuf::from(TEST_BAR_DE_PATH)).unwrap();  
-    bar.extract().unwrap();  
+    bar.extract(&std::env::current_dir().unwrap().join("managed"), &EntryFilter::new()).unwrap();  
 }
 
 #[test]
 #[ignore]
 fn extract_legacy_bar_file(){
     let bar = BarFile::open(PathBuf::from(TEST_BAR_LEGACY_PATH)).unwrap(); 
-    bar.extract().unwrap();         
-}
\ No newline at end of file
+    bar.extract(&std::env::current_dir().unwrap().join("managed"), &EntryFilter::new()).unwrap();         
+}
+
+// in-memory archive with given entries
+#[cfg(test)]
+fn build_test_bar(version: u32, entries: &[(&str, &[u8])]) -> Vec<u8> {
+    fn write_name(dest: &mut Vec<u8>, name: &str) {
+        let chars: Vec<u16> = name.encode_utf16().collect();
+        dest.extend_from_slice(&(chars.len() as u32).to_le_bytes());
+        for c in chars {
+            dest.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+    let header_size = if version == BAR_VERSION_AOE3DE { BAR_HEADER_SIZE_AOE3DE } else { BAR_HEADER_SIZE_AOE3 };
+    let data_size: usize = entries.iter().map(|(_, data)| data.len()).sum();
+    let mut bar: Vec<u8> = BINARY_SIGNATURE_BAR.to_le_bytes().to_vec();
+    bar.extend_from_slice(&version.to_le_bytes());
+    bar.extend_from_slice(&BINARY_BAR_MAGIC.to_le_bytes());
+    bar.extend_from_slice(&[0u8; 268]);
+    bar.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    if version == BAR_VERSION_AOE3DE {
+        bar.extend_from_slice(&[0u8; 4]);
+        bar.extend_from_slice(&(header_size + data_size as u64).to_le_bytes());
+        bar.extend_from_slice(&[0u8; 8]);
+    }
+    else {
+        bar.extend_from_slice(&((header_size + data_size as u64) as u32).to_le_bytes());
+        bar.extend_from_slice(&[0u8; 4]);
+    }
+    for (_, data) in entries {
+        bar.extend_from_slice(data);
+    }
+    write_name(&mut bar, "Data\\");
+    bar.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    let mut offset = header_size;
+    for (name, data) in entries {
+        if version == BAR_VERSION_AOE3DE {
+            bar.extend_from_slice(&offset.to_le_bytes());
+            bar.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bar.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bar.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        }
+        else {
+            bar.extend_from_slice(&(offset as u32).to_le_bytes());
+            bar.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bar.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            for value in [2021u16, 12, 0, 12, 12, 44, 21, 123] {
+                bar.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        write_name(&mut bar, name);
+        if version == BAR_VERSION_AOE3DE {
+            bar.extend_from_slice(&0u32.to_le_bytes());
+        }
+        offset += data.len() as u64;
+    }
+    bar
+}
+
+#[test]
+fn read_bar_from_memory(){
+    let data = build_test_bar(BAR_VERSION_AOE3DE, &[("data\\proto.xml", b"<proto/>"), ("art\\icon.ddt", b"RTS3")]);
+    let bar = BarFile::read(io::Cursor::new(data)).unwrap();
+    assert_eq!(bar.entries.len(), 2);
+    assert_eq!(bar.get_root_path().unwrap(), "Data\\");
+    assert_eq!(bar.entries[1].get_file_name().unwrap(), "art\\icon.ddt");
+    assert_eq!(bar.read_entry(&bar.entries[0]).unwrap(), b"<proto/>");
+    // truncated ddt is reported, not panicking
+    let failed_entries = bar.verify(true).unwrap();
+    assert_eq!(failed_entries.len(), 1);
+    assert_eq!(failed_entries[0].0, "art\\icon.ddt");
+}
+
+#[test]
+fn read_nested_bar(){
+    let inner = build_test_bar(BAR_VERSION_AOE3, &[("data\\proto.xml", b"<proto/>")]);
+    let outer = build_test_bar(BAR_VERSION_AOE3DE, &[("data\\readme.txt", b"readme"), ("data\\mod.bar", &inner)]);
+    let bar = BarFile::read(io::Cursor::new(outer)).unwrap();
+    // inner archive is read through entry reader, without copying it to memory
+    let nested_bar = BarFile::read(bar.entry_reader(&bar.entries[1]).unwrap()).unwrap();
+    assert_eq!(nested_bar.entries.len(), 1);
+    assert_eq!(nested_bar.entries[0].get_file_name().unwrap(), "data\\proto.xml");
+    assert_eq!(nested_bar.read_entry(&nested_bar.entries[0]).unwrap(), b"<proto/>");
+}
+
+#[test]
+fn read_bar_entries(){
+    let xml = b"<proto><unit/></proto>".repeat(10);
+    let compressed = alz4::compress(&xml);
+    let data = build_test_bar(BAR_VERSION_AOE3DE, &[("Data\\proto.xml", &compressed), ("art\\icon.ddt", b"0123456789")]);
+    let bar = BarFile::read(io::Cursor::new(data)).unwrap();
+
+    let entry = bar.get_entry("data/PROTO.xml").unwrap();
+    assert_eq!(bar.read_entry(entry).unwrap(), compressed);
+    assert_eq!(bar.read_decoded_entry(entry).unwrap(), xml);
+    assert!(bar.get_entry("data\\techtree.xml").is_none());
+
+    let entry = bar.get_entry("ART\\icon.ddt").unwrap();
+    assert_eq!(bar.read_decoded_entry(entry).unwrap(), b"0123456789");
+    let mut reader = bar.entry_reader(entry).unwrap();
+    let mut head = [0u8; 4];
+    reader.read_exact(&mut head).unwrap();
+    assert_eq!(&head, b"0123");
+    reader.seek(SeekFrom::End(-2)).unwrap();
+    let mut tail: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, b"89");
+}
+
+#[test]
+fn write_bar_file(){
+    let mut data = build_test_bar(BAR_VERSION_AOE3DE, &[("data\\proto.xml", b"<proto/>"), ("art\\icon.ddt", b"RTS3")]);
+    // unknown header fields and bytes after files table must survive
+    for (i, byte) in data[12..276].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    data[276..280].copy_from_slice(&0x11u32.to_le_bytes());
+    data[284..288].copy_from_slice(&0x33u32.to_le_bytes());
+    data[296..304].copy_from_slice(&0x5544u64.to_le_bytes());
+    data.extend_from_slice(b"tail");
+    let bar = BarFile::read(io::Cursor::new(data.clone())).unwrap();
+    let mut written: Vec<u8> = Vec::new();
+    bar.write(&mut written).unwrap();
+    assert_eq!(written, data);
+
+    let data = build_test_bar(BAR_VERSION_AOE3, &[("sound\\attack.wav", b"RIFF....WAVE")]);
+    let bar = BarFile::read(io::Cursor::new(data.clone())).unwrap();
+    let mut written: Vec<u8> = Vec::new();
+    bar.write(&mut written).unwrap();
+    assert_eq!(written, data);
+}
+
+#[test]
+fn open_broken_bar_file(){
+    assert!(matches!(BarFile::read(io::Cursor::new(b"ESPN".to_vec())), Err(ResourceError::TruncatedHeader(FileFormat::Bar))));
+
+    let mut data: Vec<u8> = BINARY_SIGNATURE_BAR.to_le_bytes().to_vec();
+    data.extend_from_slice(&7u32.to_le_bytes());
+    assert!(matches!(BarFile::read(io::Cursor::new(data)), Err(ResourceError::UnsupportedVersion(7))));
+
+    // files table offset points past the end of file
+    let mut data: Vec<u8> = BINARY_SIGNATURE_BAR.to_le_bytes().to_vec();
+    data.extend_from_slice(&BAR_VERSION_AOE3.to_le_bytes());
+    data.extend_from_slice(&BINARY_BAR_MAGIC.to_le_bytes());
+    data.extend_from_slice(&[0u8; 268]);
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&1000u32.to_le_bytes());
+    data.extend_from_slice(&[0u8; 4]);
+    assert!(matches!(BarFile::read(io::Cursor::new(data.clone())), Err(ResourceError::OffsetOutOfRange { offset: 1000, .. })));
+
+    // files table with one entry cut in the middle
+    let mut data = data[..data.len() - 8].to_vec();
+    data.extend_from_slice(&(BAR_HEADER_SIZE_AOE3 as u32).to_le_bytes());
+    data.extend_from_slice(&[0u8; 4]);
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&[0u8; 6]);
+    assert!(matches!(BarFile::read(io::Cursor::new(data)), Err(ResourceError::TruncatedTable { index: 0, .. })));
+
+    let path = std::env::temp_dir().join("resource_manager_missing.bar");
+    assert!(matches!(BarFile::open(path), Err(ResourceError::BarNotFound(_))));
+}
+
+#[test]
+fn edit_bar_entries(){
+    for version in [BAR_VERSION_AOE3DE, BAR_VERSION_AOE3] {
+        let data = build_test_bar(version, &[("data\\proto.xml", b"<proto/>"), ("art\\icon.ddt", b"RTS3"), ("data\\techtree.xml", b"<techtree/>")]);
+        let mut bar = BarFile::read(io::Cursor::new(data)).unwrap();
+        let compressed = alz4::compress(b"<proto><unit/></proto>");
+        bar.replace_entry("data/proto.xml", compressed.clone()).unwrap();
+        bar.delete_entry("art\\icon.ddt").unwrap();
+        bar.rename_entry("data\\techtree.xml", "data/game/techtree.xml").unwrap();
+        bar.add_entry("sound/attack.wav", b"RIFF....WAVE".to_vec()).unwrap();
+        assert!(matches!(bar.add_entry("Data\\Proto.xml", Vec::new()), Err(ResourceError::EntryExists(_))));
+        assert!(matches!(bar.delete_entry("art\\icon.ddt"), Err(ResourceError::EntryNotFound(_))));
+        assert!(matches!(bar.rename_entry("sound\\attack.wav", "data\\proto.xml"), Err(ResourceError::EntryExists(_))));
+        for name in ["..\\evil.txt", "data\\..\\..\\evil.txt", "\\evil.txt", "C:\\evil.txt", "/tmp/evil.txt", "data\\", ""] {
+            assert!(matches!(bar.add_entry(name, Vec::new()), Err(ResourceError::UnsafeEntryPath(_))));
+            assert!(matches!(bar.rename_entry("sound\\attack.wav", name), Err(ResourceError::UnsafeEntryPath(_))));
+        }
+        // pending data is readable before archive is written
+        assert_eq!(bar.read_decoded_entry(bar.get_entry("data\\proto.xml").unwrap()).unwrap(), b"<proto><unit/></proto>");
+
+        let mut written: Vec<u8> = Vec::new();
+        bar.write(&mut written).unwrap();
+        let bar = BarFile::read(io::Cursor::new(written)).unwrap();
+        assert_eq!(bar.entries.len(), 3);
+        assert!(bar.get_entry("art\\icon.ddt").is_none());
+        let entry = bar.get_entry("data\\proto.xml").unwrap();
+        assert_eq!(bar.read_entry(entry).unwrap(), compressed);
+        if version == BAR_VERSION_AOE3DE {
+            assert_eq!(entry.is_encoded, 1);
+            assert_eq!(entry.file_size1, 22);
+        }
+        let entry = bar.get_entry("data\\game\\techtree.xml").unwrap();
+        assert_eq!(entry.get_file_name_lossy(), "data\\game\\techtree.xml");
+        assert_eq!(bar.read_entry(entry).unwrap(), b"<techtree/>");
+        assert_eq!(bar.read_entry(bar.get_entry("sound\\attack.wav").unwrap()).unwrap(), b"RIFF....WAVE");
+    }
+
+    // legacy offsets past 4 GiB are rejected before anything is written
+    let data = build_test_bar(BAR_VERSION_AOE3, &[("data\\proto.xml", b"<proto/>"), ("data\\techtree.xml", b"<techtree/>")]);
+    let mut bar = BarFile::read(io::Cursor::new(data)).unwrap();
+    bar.entries[0].file_size2 = u32::MAX;
+    bar.entries[1].file_size2 = u32::MAX;
+    bar.modified = true;
+    let mut written: Vec<u8> = Vec::new();
+    assert!(matches!(bar.write(&mut written), Err(ResourceError::OffsetTooLarge(_))));
+    assert!(written.is_empty());
+}
+
+#[test]
+fn rewrite_bar_file(){
+    let path = std::env::temp_dir().join("resource_manager_rewrite.bar");
+    fs::write(&path, build_test_bar(BAR_VERSION_AOE3DE, &[("data\\proto.xml", b"<proto/>")])).unwrap();
+    let mut bar = BarFile::open(path.clone()).unwrap();
+    bar.add_entry("art\\icon.ddt", b"RTS3".to_vec()).unwrap();
+    bar.rewrite().unwrap();
+    assert_eq!(bar.entries.len(), 2);
+    let bar = BarFile::open(path.clone()).unwrap();
+    assert_eq!(bar.read_entry(bar.get_entry("art\\icon.ddt").unwrap()).unwrap(), b"RTS3");
+    assert_eq!(bar.read_entry(bar.get_entry("data\\proto.xml").unwrap()).unwrap(), b"<proto/>");
+    drop(bar);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn append_and_compact_bar_file(){
+    for version in [BAR_VERSION_AOE3DE, BAR_VERSION_AOE3] {
+        let path = std::env::temp_dir().join(format!("resource_manager_append_{}.bar", version));
+        let data = build_test_bar(version, &[("data\\proto.xml", b"<proto/>"), ("art\\icon.ddt", b"RTS3")]);
+        fs::write(&path, &data).unwrap();
+        let mut bar = BarFile::open(path.clone()).unwrap();
+        bar.replace_entry("data\\proto.xml", b"<proto><unit/></proto>".to_vec()).unwrap();
+        bar.add_entry("sound\\attack.wav", b"RIFF....WAVE".to_vec()).unwrap();
+        bar.append().unwrap();
+        // old data is kept in front of appended one
+        let header_size = if version == BAR_VERSION_AOE3DE { BAR_HEADER_SIZE_AOE3DE } else { BAR_HEADER_SIZE_AOE3 } as usize;
+        assert_eq!(fs::read(&path).unwrap()[header_size..data.len()], data[header_size..]);
+        assert_eq!(bar.entries.len(), 3);
+        assert_eq!(bar.read_entry(bar.get_entry("data\\proto.xml").unwrap()).unwrap(), b"<proto><unit/></proto>");
+        assert_eq!(bar.read_entry(bar.get_entry("art\\icon.ddt").unwrap()).unwrap(), b"RTS3");
+
+        let appended_size = fs::metadata(&path).unwrap().len();
+        let reclaimed = bar.compact().unwrap();
+        assert!(reclaimed > 0);
+        assert_eq!(fs::metadata(&path).unwrap().len(), appended_size - reclaimed);
+        assert_eq!(bar.read_entry(bar.get_entry("data\\proto.xml").unwrap()).unwrap(), b"<proto><unit/></proto>");
+        assert_eq!(bar.read_entry(bar.get_entry("sound\\attack.wav").unwrap()).unwrap(), b"RIFF....WAVE");
+        assert_eq!(bar.compact().unwrap(), 0);
+        // nothing to append
+        bar.append().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), appended_size - reclaimed);
+        drop(bar);
+        fs::remove_file(&path).unwrap();
+    }
+
+    let mut bar = BarFile::read(io::Cursor::new(build_test_bar(BAR_VERSION_AOE3DE, &[("data\\proto.xml", b"<proto/>")]))).unwrap();
+    assert!(matches!(bar.compact(), Err(ResourceError::InvalidPath(_))));
+
+    // legacy archive grown past 4 GiB (sparse file) can't get new files table
+    let path = std::env::temp_dir().join("resource_manager_append_large.bar");
+    fs::write(&path, build_test_bar(BAR_VERSION_AOE3, &[("data\\proto.xml", b"<proto/>")])).unwrap();
+    let mut bar = BarFile::open(path.clone()).unwrap();
+    let large_size = u32::MAX as u64 + 1;
+    File::options().write(true).open(&path).unwrap().set_len(large_size).unwrap();
+    bar.add_entry("data\\techtree.xml", b"<techtree/>".to_vec()).unwrap();
+    assert!(matches!(bar.append(), Err(ResourceError::OffsetTooLarge(_))));
+    assert_eq!(fs::metadata(&path).unwrap().len(), large_size);
+    drop(bar);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn keep_legacy_entry_time(){
+    let root = std::env::temp_dir().join("resource_manager_time");
+    let _ = fs::remove_dir_all(&root);
+    let dir = root.join("Data");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("proto.xml");
+    fs::write(&path, b"<proto/>").unwrap();
+    let modified_time = std::time::UNIX_EPOCH + std::time::Duration::from_millis(1639313061123);
+    File::options().write(true).open(&path).unwrap().set_modified(modified_time).unwrap();
+
+    let managed_path = root.join("managed");
+    let bar = BarFile::create(dir, &managed_path, BAR_VERSION_AOE3, &[]).unwrap();
+    let bar = BarFile::open(bar.bar_path.clone()).unwrap();
+    assert_eq!(bar.entries[0].get_date_time().to_system_time(), Some(modified_time));
+
+    bar.extract(&managed_path, &EntryFilter::new()).unwrap();
+    let extracted_path = bar.entries[0].get_path(&managed_path.join("extracted"), &bar.root_path).unwrap();
+    assert_eq!(fs::metadata(extracted_path).unwrap().modified().unwrap(), modified_time);
+    drop(bar);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn create_de_bar_sizes(){
+    let root = std::env::temp_dir().join("resource_manager_sizes");
+    let _ = fs::remove_dir_all(&root);
+    let dir = root.join("Data");
+    fs::create_dir_all(&dir).unwrap();
+    let xml = b"<proto><unit/></proto>".repeat(10);
+    fs::write(dir.join("proto.xml"), &xml).unwrap();
+    fs::write(dir.join("techtree.xml"), alz4::compress(&xml)).unwrap();
+    fs::write(dir.join("icon.ddt"), b"RTS3").unwrap();
+    let wav = b"RIFF\x04\x00\x00\x00WAVE".to_vec();
+    fs::write(dir.join("attack.wav"), &wav).unwrap();
+    let webp = b"RIFF\x04\x00\x00\x00WEBPVP8 ".to_vec();
+    fs::write(dir.join("icon.webp"), &webp).unwrap();
+
+    let bar = BarFile::create(dir, &root.join("managed"), BAR_VERSION_AOE3DE, &["*proto.xml".to_owned()]).unwrap();
+    let bar = BarFile::open(bar.bar_path.clone()).unwrap();
+    for name in ["proto.xml", "techtree.xml"] {
+        let entry = bar.get_entry(name).unwrap();
+        assert_eq!(entry.is_encoded, 1);
+        assert_eq!(entry.file_size1 as usize, xml.len());
+        assert!(entry.file_size2 < entry.file_size1);
+    }
+    let entry = bar.get_entry("icon.ddt").unwrap();
+    assert_eq!((entry.file_size1, entry.file_size2, entry.file_size3), (4, 4, 4));
+    // plain wav is encrypted when archive is built
+    let entry = bar.get_entry("attack.wav").unwrap();
+    assert_eq!(entry.is_encoded, 2);
+    assert_eq!(entry.file_size1 as usize, wav.len());
+    assert_ne!(bar.read_entry(entry).unwrap(), wav);
+    assert_eq!(bar.read_decoded_entry(entry).unwrap(), wav);
+    // other riff containers are stored as they are
+    let entry = bar.get_entry("icon.webp").unwrap();
+    assert_eq!(entry.is_encoded, 0);
+    assert_eq!(bar.read_entry(entry).unwrap(), webp);
+    drop(bar);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn keep_entry_names_on_any_os(){
+    let root = std::env::temp_dir().join("resource_manager_names");
+    let _ = fs::remove_dir_all(&root);
+    let dir = root.join("Данные");
+    fs::create_dir_all(dir.join("art").join("юниты")).unwrap();
+    fs::write(dir.join("art").join("юниты").join("икона.txt"), b"icon").unwrap();
+
+    let managed_path = root.join("managed");
+    let bar = BarFile::create(dir, &managed_path, BAR_VERSION_AOE3DE, &[]).unwrap();
+    let bar = BarFile::open(bar.bar_path.clone()).unwrap();
+    assert_eq!(bar.get_root_path().unwrap(), "Данные\\");
+    assert_eq!(bar.entries[0].get_file_name().unwrap(), "art\\юниты\\икона.txt");
+    assert_eq!(bar.entries[0].file_name_length, 19);
+
+    bar.extract(&managed_path, &EntryFilter::new()).unwrap();
+    let extracted_path = managed_path.join("extracted").join("Данные").join("art").join("юниты").join("икона.txt");
+    assert_eq!(bar.entries[0].get_path(&managed_path.join("extracted"), &bar.root_path).unwrap(), extracted_path);
+    assert_eq!(fs::read(extracted_path).unwrap(), b"icon");
+    drop(bar);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn reject_unsafe_entry_paths(){
+    let root = std::env::temp_dir().join("resource_manager_unsafe");
+    let _ = fs::remove_dir_all(&root);
+    let managed_path = root.join("managed");
+    for name in ["..\\..\\evil.txt", "art\\..\\..\\evil.txt", "\\evil.txt", "C:\\evil.txt", "/tmp/evil.txt"] {
+        let data = build_test_bar(BAR_VERSION_AOE3DE, &[(name, b"evil")]);
+        let bar = BarFile::read(io::Cursor::new(data)).unwrap();
+        match bar.extract(&managed_path, &EntryFilter::new()) {
+            Err(ResourceError::Entry { name: entry_name, source, .. }) => {
+                assert_eq!(entry_name, name);
+                assert!(matches!(*source, ResourceError::UnsafeEntryPath(_)));
+            },
+            _ => panic!("unsafe entry path is not rejected: {}", name),
+        }
+    }
+    assert!(!root.join("evil.txt").exists());
+
+    // trusted archive is extracted as it is
+    let data = build_test_bar(BAR_VERSION_AOE3DE, &[("..\\trusted.txt", b"trusted")]);
+    let mut bar = BarFile::read(io::Cursor::new(data)).unwrap();
+    bar.trust_entry_paths = true;
+    bar.extract(&managed_path, &EntryFilter::new()).unwrap();
+    assert_eq!(fs::read(managed_path.join("extracted").join("trusted.txt")).unwrap(), b"trusted");
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn verify_bar_structure(){
+    let compressed = alz4::compress(b"<proto/>");
+    let data = build_test_bar(BAR_VERSION_AOE3DE, &[("data\\proto.xml", &compressed), ("art\\icon.ddt", b"RTS3"), ("data\\techtree.xml", b"<techtree/>")]);
+    let mut bar = BarFile::read(io::Cursor::new(data)).unwrap();
+    bar.entries[0].is_encoded = 1;
+    // consistent table is valid, except truncated ddt found by full check
+    assert_eq!(bar.verify(false).unwrap().len(), 0);
+    assert_eq!(bar.verify(true).unwrap().len(), 1);
+
+    bar.entries[0].is_encoded = 0;
+    bar.entries[1].offset -= 2;
+    bar.entries[2].file_name = bar.entries[0].file_name.clone();
+    let failed_entries = bar.verify(false).unwrap();
+    assert_eq!(failed_entries.len(), 3);
+    assert!(failed_entries[0].1.starts_with(ERR_ENCODING_MISMATCH));
+    assert_eq!(failed_entries[1].1, ERR_DUPLICATE_ENTRY);
+    assert_eq!(failed_entries[2], ("art\\icon.ddt".to_owned(), format!("{} data\\proto.xml", ERR_ENTRY_OVERLAP)));
+
+    bar.entries[1].file_size2 = 0;
+    bar.entries[2].offset = bar.files_table_offset;
+    let failed_entries = bar.verify(false).unwrap();
+    assert_eq!(failed_entries[1], ("art\\icon.ddt".to_owned(), ERR_EMPTY_ENTRY.to_owned()));
+    assert!(failed_entries[3].1.starts_with(ERR_ENTRY_OUT_OF_DATA));
+
+    // offset near end of u64 range must not overflow
+    bar.entries[2].offset = u64::MAX - 4;
+    let failed_entries = bar.verify(false).unwrap();
+    assert!(failed_entries[3].1.starts_with(ERR_ENTRY_OUT_OF_DATA));
+}
+
+#[test]
+fn hash_bar_entries(){
+    let xml = b"<proto><unit/></proto>".to_vec();
+    let compressed = alz4::compress(&xml);
+    let data = build_test_bar(BAR_VERSION_AOE3DE, &[("data\\proto.xml", &compressed), ("art\\icon.ddt", b"RTS3")]);
+    let bar = BarFile::read(io::Cursor::new(data)).unwrap();
+    let hashes = bar.hash_entries(&EntryFilter::new()).unwrap();
+    assert_eq!(hashes.len(), 2);
+    assert_eq!(hashes[0].stored, ContentHash::new(&compressed));
+    assert_eq!(hashes[0].decoded, ContentHash::new(&xml));
+    assert_eq!(hashes[1].stored, hashes[1].decoded);
+
+    // same content stored without compression is not a change
+    let data = build_test_bar(BAR_VERSION_AOE3DE, &[("data\\proto.xml", &xml), ("art\\icon.ddt", b"RTS4")]);
+    let patched_bar = BarFile::read(io::Cursor::new(data)).unwrap();
+    let differences = crate::hash::compare(&hashes, &patched_bar.hash_entries(&EntryFilter::new()).unwrap());
+    assert_eq!(differences, vec![crate::hash::HashDifference::Changed("art\\icon.ddt".to_owned())]);
+}
+
+#[test]
+fn create_bar_in_sorted_order(){
+    let root = std::env::temp_dir().join("resource_manager_order");
+    let _ = fs::remove_dir_all(&root);
+    let dir = root.join("Data");
+    fs::create_dir_all(dir.join("A")).unwrap();
+    for path in ["b.xml", "C.xml", "a_d.xml", "A/c.xml"] {
+        fs::write(dir.join(path), b"<x/>").unwrap();
+    }
+    let bar = BarFile::create(dir, &root.join("managed"), BAR_VERSION_AOE3DE, &[]).unwrap();
+    let names: Vec<String> = bar.entries.iter().map(|entry| entry.get_file_name_lossy()).collect();
+    assert_eq!(names, ["A\\c.xml", "a_d.xml", "b.xml", "C.xml"]);
+    drop(bar);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+// relative paths and contents of all files in directory
+#[cfg(test)]
+fn read_tree(dir: &PathBuf) -> Vec<(PathBuf, Vec<u8>)> {
+    let mut files: Vec<(PathBuf, Vec<u8>)> = Vec::new();
+    for raw_entry in get_raw_bar_entries_in_directory(dir).unwrap() {
+        files.push((raw_entry.path.strip_prefix(dir).unwrap().to_path_buf(), fs::read(&raw_entry.path).unwrap()));
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn extract_only_valid_xmb_entries(){
+    let root = std::env::temp_dir().join("resource_manager_xmb");
+    let _ = fs::remove_dir_all(&root);
+    let xmb = XmbFile::from_xml("<proto><unit/></proto>").unwrap().to_bytes();
+    let mut broken_xmb = xmb.clone();
+    broken_xmb.truncate(12);
+    broken_xmb[2..6].copy_from_slice(&6u32.to_le_bytes());
+    let data = build_test_bar(BAR_VERSION_AOE3DE, &[("data\\notes.txt", &xmb), ("data\\broken.xmb", &broken_xmb), ("data\\proto.xml.xmb", &xmb)]);
+    let bar = BarFile::read(io::Cursor::new(data)).unwrap();
+    bar.extract(&root, &EntryFilter::new()).unwrap();
+    // every entry is extracted, only valid .xmb is converted
+    for name in ["notes.txt", "broken.xmb", "proto.xml.xmb"] {
+        assert!(root.join("extracted").join("Data").join("data").join(name).exists());
+    }
+    assert_eq!(read_tree(&root.join("converted")).len(), 1);
+    assert!(root.join("converted").join("Data").join("data").join("proto.xml").exists());
+
+    let failed_entries = bar.verify(true).unwrap();
+    assert_eq!(failed_entries.len(), 1);
+    assert_eq!(failed_entries[0].0, "data\\broken.xmb");
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn multiextract_same_as_extract(){
+    let root = std::env::temp_dir().join("resource_manager_multiextract");
+    let _ = fs::remove_dir_all(&root);
+    let xml = b"<proto><unit/></proto>".repeat(10);
+    let compressed = alz4::compress(&xml);
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    for i in 0..40 {
+        entries.push((format!("data\\part{}\\proto{}.xml", i % 3, i), if i % 2 == 0 { compressed.clone() } else { xml.clone() }));
+    }
+    // duplicates: last entry of files table wins
+    for i in 0..8 {
+        entries.push(("Data\\Duplicate.xml".to_owned(), format!("<duplicate id=\"{}\"/>", i).into_bytes()));
+    }
+    let entries: Vec<(&str, &[u8])> = entries.iter().map(|(name, data)| (name.as_str(), data.as_slice())).collect();
+    let bar = BarFile::read(io::Cursor::new(build_test_bar(BAR_VERSION_AOE3DE, &entries))).unwrap();
+
+    bar.extract(&root.join("sequential"), &EntryFilter::new()).unwrap();
+    bar.multiextract(&root.join("parallel"), 4, &EntryFilter::new()).unwrap();
+    let sequential_tree = read_tree(&root.join("sequential"));
+    assert_eq!(sequential_tree, read_tree(&root.join("parallel")));
+    let duplicate = sequential_tree.iter().find(|(path, _)| path.ends_with("Duplicate.xml")).unwrap();
+    assert_eq!(duplicate.1, b"<duplicate id=\"7\"/>");
+    fs::remove_dir_all(&root).unwrap();
+}
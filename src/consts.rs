@@ -26,6 +26,9 @@ pub const DDT_FORMAT_DXT5: u8 = 9;
 pub const BAR_VERSION_AOE3: u32 = 2; // Legacy
 pub const BAR_VERSION_AOE3DE: u32 = 6; // DE
 
+pub const BAR_HEADER_SIZE_AOE3: u64 = 292; // Legacy
+pub const BAR_HEADER_SIZE_AOE3DE: u64 = 304; // DE
+
 pub const ENCODE_TYPE_NONE: u32 = 0; // raw data
 pub const ENCODE_TYPE_ALZ4_L33T: u32 = 1; // alz4 or l33t encoding
 pub const ENCODE_TYPE_SND: u32 = 2; // sound file encoding
@@ -38,5 +41,8 @@ pub const BINARY_SIGNATURE_WAV_ENCODED: u32 = 0xB4428C6D;
 pub const BINARY_SIGNATURE_MP3: u32 = 0x334449;
 pub const BINARY_SIGNATURE_BAR: u32 = 0x4E505345;
 pub const BINARY_SIGNATURE_DDT: u32 = 0x33535452;
+pub const BINARY_SIGNATURE_XMB: u16 = 0x3158; // X1
+pub const BINARY_SIGNATURE_XMB_ROOT: u16 = 0x5258; // XR
+pub const BINARY_SIGNATURE_XMB_NODE: u16 = 0x4E58; // XN
 
 pub const BINARY_BAR_MAGIC: u32 = 0x44332211;
\ No newline at end of file
@@ -0,0 +1,103 @@
+use crate::bar::BarFile;
+use crate::ddt::DdtFile;
+use crate::xmb::{self, XmbFile};
+use crate::alz4;
+use crate::l33t;
+use crate::sound;
+
+use crate::consts::BINARY_SIGNATURE_DDT;
+use crate::consts::BINARY_SIGNATURE_ALZ4;
+use crate::consts::BINARY_SIGNATURE_L33T;
+use crate::consts::BINARY_SIGNATURE_WAV_ENCODED;
+use crate::consts::BINARY_SIGNATURE_WAV_DECODED;
+
+use crate::error::ResourceError;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// converted file goes to output folder with name of source file
+fn get_output_path(path: &Path, out_dir: &Path) -> Result<PathBuf, ResourceError> {
+    let file_name = path.file_name().ok_or_else(|| ResourceError::InvalidPath(path.to_path_buf()))?;
+    fs::create_dir_all(out_dir)?;
+    Ok(out_dir.join(file_name))
+}
+
+// unwrapped data is saved as it is, xmb inside is also converted to xml
+fn save_decompressed(data: &[u8], output_path: PathBuf) -> Result<(), ResourceError> {
+    fs::write(&output_path, data)?;
+    if xmb::is_xmb(data) {
+        XmbFile::read(data)?.save_xml(XmbFile::xml_path(output_path))?;
+    }
+    Ok(())
+}
+
+// decode, encode or convert single file depending on its signature and write result to out_dir:
+// DDT -> TGA, encoded WAV <-> WAV, alz4/l33t -> unwrapped data (and XML), XMB -> XML, XML -> XMB.
+// compress_xmb wraps XMB converted from XML with alz4. false if file is not supported
+pub fn convert_file(path: &Path, out_dir: &Path, compress_xmb: bool) -> Result<bool, ResourceError> {
+    let data = fs::read(path)?;
+    let signature = match data.get(..4) {
+        Some(signature) => u32::from_le_bytes([signature[0], signature[1], signature[2], signature[3]]),
+        None => 0,
+    };
+    match signature {
+        BINARY_SIGNATURE_DDT => {
+            let tga_file = DdtFile::read(&data)?.to_tga()?;
+            tga_file.save(get_output_path(path, out_dir)?.with_extension("tga"))?;
+        },
+        BINARY_SIGNATURE_WAV_ENCODED => {
+            fs::write(get_output_path(path, out_dir)?, BarFile::decode_sound(&data)?)?;
+        },
+        // other RIFF files (webp, avi) are not sounds
+        BINARY_SIGNATURE_WAV_DECODED if sound::is_wav(&data) => {
+            fs::write(get_output_path(path, out_dir)?, BarFile::encode_sound(&data)?)?;
+        },
+        BINARY_SIGNATURE_ALZ4 => {
+            save_decompressed(&alz4::decompress(&data)?, get_output_path(path, out_dir)?)?;
+        },
+        BINARY_SIGNATURE_L33T => {
+            save_decompressed(&l33t::decompress(&data)?, get_output_path(path, out_dir)?)?;
+        },
+        _ if xmb::is_xmb(&data) => {
+            XmbFile::read(&data)?.save_xml(XmbFile::xml_path(get_output_path(path, out_dir)?))?;
+        },
+        _ if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("xml")) => {
+            let xmb_file = XmbFile::open_xml(path.to_path_buf())?;
+            xmb_file.save(XmbFile::xmb_path(get_output_path(path, out_dir)?), compress_xmb)?;
+        },
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+#[test]
+fn convert_loose_files() {
+    let root = std::env::temp_dir().join("resource_manager_convert");
+    let _ = fs::remove_dir_all(&root);
+    let out_dir = root.join("converted");
+    fs::create_dir_all(&root).unwrap();
+
+    let xml_path = root.join("proto.xml");
+    fs::write(&xml_path, "<proto><unit id=\"1\"/></proto>").unwrap();
+    assert!(convert_file(&xml_path, &out_dir, true).unwrap());
+    let compressed = fs::read(out_dir.join("proto.xml.XMB")).unwrap();
+    assert_eq!(crate::bar::get_file_signature(&compressed, 4), BINARY_SIGNATURE_ALZ4);
+
+    // alz4 is unwrapped and xmb inside is converted back to xml
+    let compressed_path = root.join("proto.xml.XMB");
+    fs::write(&compressed_path, &compressed).unwrap();
+    fs::remove_dir_all(&out_dir).unwrap();
+    assert!(convert_file(&compressed_path, &out_dir, false).unwrap());
+    assert!(xmb::is_xmb(&fs::read(out_dir.join("proto.xml.XMB")).unwrap()));
+    assert_eq!(fs::read_to_string(out_dir.join("proto.xml")).unwrap(), "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<proto>\n  <unit id=\"1\"/>\n</proto>\n");
+
+    let webp_path = root.join("icon.webp");
+    fs::write(&webp_path, b"RIFF\x04\x00\x00\x00WEBPVP8 ").unwrap();
+    fs::remove_dir_all(&out_dir).unwrap();
+    assert!(!convert_file(&webp_path, &out_dir, false).unwrap());
+    assert!(!out_dir.exists());
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,90 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// same layout as windows SYSTEMTIME, in UTC
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u16,
+    pub day_of_week: u16, // 0 - sunday
+    pub day: u16,
+    pub hour: u16,
+    pub minute: u16,
+    pub second: u16,
+    pub msecond: u16,
+}
+
+// days since 1970-01-01 to civil date (Howard Hinnant's algorithm)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// civil date to days since 1970-01-01
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+impl DateTime {
+    pub fn from_system_time(time: SystemTime) -> DateTime {
+        let millis: i64 = match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_millis() as i64,
+            Err(error) => -(error.duration().as_millis() as i64),
+        };
+        let days = millis.div_euclid(86_400_000);
+        let millis_of_day = millis.rem_euclid(86_400_000);
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year: year as u16,
+            month: month as u16,
+            day_of_week: (days + 4).rem_euclid(7) as u16,
+            day: day as u16,
+            hour: (millis_of_day / 3_600_000) as u16,
+            minute: (millis_of_day / 60_000 % 60) as u16,
+            second: (millis_of_day / 1000 % 60) as u16,
+            msecond: (millis_of_day % 1000) as u16,
+        }
+    }
+
+    // None for zero or invalid dates
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        if self.year < 1970 || self.month < 1 || self.month > 12 || self.day < 1 || self.day > 31
+            || self.hour > 23 || self.minute > 59 || self.second > 59 || self.msecond > 999 {
+            return None;
+        }
+        let days = days_from_civil(self.year as i64, self.month as u32, self.day as u32);
+        let millis = days as u64 * 86_400_000 + self.hour as u64 * 3_600_000 + self.minute as u64 * 60_000
+            + self.second as u64 * 1000 + self.msecond as u64;
+        Some(UNIX_EPOCH + Duration::from_millis(millis))
+    }
+
+    // file name friendly timestamp: 2021-12-12_12-44-21-123
+    pub fn to_file_name(&self) -> String {
+        format!("{:04}-{:02}-{:02}_{:02}-{:02}-{:02}-{:03}", self.year, self.month, self.day, self.hour, self.minute, self.second, self.msecond)
+    }
+}
+
+
+#[test]
+fn convert_system_time() {
+    let time = UNIX_EPOCH + Duration::from_millis(1639313061123);
+    let date_time = DateTime::from_system_time(time);
+    assert_eq!(date_time, DateTime { year: 2021, month: 12, day_of_week: 0, day: 12, hour: 12, minute: 44, second: 21, msecond: 123 });
+    assert_eq!(date_time.to_system_time(), Some(time));
+    assert_eq!(date_time.to_file_name(), "2021-12-12_12-44-21-123");
+    assert_eq!(DateTime::from_system_time(UNIX_EPOCH + Duration::from_secs(951782400)).day, 29);
+    assert_eq!(DateTime { year: 0, month: 0, day_of_week: 0, day: 0, hour: 0, minute: 0, second: 0, msecond: 0 }.to_system_time(), None);
+}
@@ -1,19 +1,17 @@
-mod dxt;
-mod tga;
+pub mod dxt;
+pub mod tga;
 
 use crate::ddt::dxt::DxtImage;
 use crate::ddt::tga::TgaFile;
 
-use crate::loc::ERR_NOT_VALID_DDT_SIGNATURE;
-use crate::loc::ERR_NOT_VALID_DDT_FORMAT;
+use crate::error::{ResourceError, FileFormat};
 use std::fs::File;
-use std::{
-    cmp,
-    error::Error,
-};
+use std::cmp;
 use std::io::SeekFrom;
 use std::io::Cursor;
 use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
 use std::io::Read;
 use std::io::Seek;
 use std::path::PathBuf;
@@ -28,20 +26,20 @@ use crate::consts::DDT_FORMAT_DXT5;
 
 
 pub struct DdtFile {
-    signature: u32,
-    usage: u8,
-    alpha: u8,
-    format: u8,
-    mipmap_levels: u8,
+    pub signature: u32,
+    pub usage: u8,
+    pub alpha: u8,
+    pub format: u8,
+    pub mipmap_levels: u8,
     pub base_width: u32,
     pub base_height: u32,
-    images: Vec<DxtImage>,
+    pub images: Vec<DxtImage>,
 }
 
 impl DdtFile {
 
-    fn decode(&self) -> Result<Vec<u8>, Box<dyn Error>> {
-        let ddt_image = self.images.first().unwrap();
+    fn decode(&self) -> Result<Vec<u8>, ResourceError> {
+        let ddt_image = self.images.first().ok_or(ResourceError::CorruptedData(FileFormat::Ddt))?;
         let decoded_ddt_image: Vec<u8>;
 
         match self.format {
@@ -52,35 +50,39 @@ impl DdtFile {
                 decoded_ddt_image = ddt_image.raw_data.to_vec();
             },
             _ => {
-                panic!("{}", ERR_NOT_VALID_DDT_FORMAT);
+                return Err(ResourceError::UnsupportedDdtFormat(self.format));
             }
         }
         return Ok(decoded_ddt_image);
     }
 
 
-    fn encode(source: &[u8], width: u16, height: u16, usage: u8, format: u8) -> Result<Vec<u8>, Box<dyn Error>> {
+    fn encode(source: &[u8], width: u16, height: u16, usage: u8, format: u8) -> Result<Vec<u8>, ResourceError> {
         let encoded_ddt_image: Vec<u8>;
 
         match format {
             DDT_FORMAT_DXT1 | DDT_FORMAT_DXT1DE | DDT_FORMAT_DXT3 | DDT_FORMAT_DXT5 => {
-                encoded_ddt_image = DxtImage::compress(source, format, usage, width as u32, height as u32);
+                encoded_ddt_image = DxtImage::compress(source, format, usage, width as u32, height as u32)?;
             },
             DDT_FORMAT_BGRA | DDT_FORMAT_GREY => {
                 encoded_ddt_image = source.to_vec();
             },
             _ => {
-                panic!("{}", ERR_NOT_VALID_DDT_FORMAT);
+                return Err(ResourceError::UnsupportedDdtFormat(format));
             }
         }
         return Ok(encoded_ddt_image);
     }
 
-    pub fn to_tga(&self) -> Result<TgaFile, Box<dyn Error>> {
+    pub fn to_tga(&self) -> Result<TgaFile, ResourceError> {
+        // tga header holds size in u16
+        if self.base_width > u16::MAX as u32 || self.base_height > u16::MAX as u32 {
+            return Err(ResourceError::CorruptedData(FileFormat::Ddt));
+        }
         return Ok(TgaFile::new(self.base_width as u16, self.base_height as u16, self.usage, self.alpha, self.format, self.mipmap_levels, self.decode()?));
     }
 
-    pub fn from_tga(path: PathBuf) -> Result<DdtFile, Box<dyn Error>> {
+    pub fn from_tga(path: PathBuf) -> Result<DdtFile, ResourceError> {
         let tga_file = TgaFile::open(path)?;
         let dxt_image_vec = DdtFile::encode(&tga_file.raw_data, tga_file.image_width, tga_file.image_height, tga_file.image_id[0], tga_file.image_id[2])?;
 
@@ -104,13 +106,13 @@ impl DdtFile {
 
     }
 
-    pub fn save(&self, path: PathBuf) -> Result<(), Box<dyn Error>> {
+    pub fn save(&self, path: PathBuf) -> Result<(), ResourceError> {
         let mut writer = BufWriter::new(File::create(&path)?);
         writer.write(&self.to_bytes())?;
         return Ok(());
     }
 
-    fn to_bytes(&self) -> Vec<u8> {
+    pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes: Vec<u8> = Vec::new();
 
         bytes.extend_from_slice(&self.signature.to_le_bytes());
@@ -120,17 +122,31 @@ impl DdtFile {
         bytes.extend_from_slice(&self.mipmap_levels.to_le_bytes());
         bytes.extend_from_slice(&self.base_width.to_le_bytes());
         bytes.extend_from_slice(&self.base_height.to_le_bytes());
-        bytes.extend_from_slice(&self.images.to_bytes());
+        let mut offset: u32 = 16 + 8 * self.images.len() as u32;
+        for image in &self.images {
+            let length = image.raw_data.len() as u32;
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+            offset += length;
+        }
+        for image in &self.images {
+            bytes.extend_from_slice(&image.raw_data);
+        }
         return bytes;
     }
 
-    pub fn read(data: &[u8]) -> Result<DdtFile, Box<dyn Error>> {
+    pub fn read(data: &[u8]) -> Result<DdtFile, ResourceError> {
+        if data.len() < 16 {
+            return Err(ResourceError::TruncatedHeader(FileFormat::Ddt));
+        }
         let mut reader = BufReader::new(Cursor::new(data));
         let mut signature = [0u8; 4];
         reader.read_exact (&mut signature)?;
         let signature: u32 = u32::from_le_bytes(signature);
 
-        assert_eq!(signature, BINARY_SIGNATURE_DDT, "{}", ERR_NOT_VALID_DDT_SIGNATURE);
+        if signature != BINARY_SIGNATURE_DDT {
+            return Err(ResourceError::BadSignature { format: FileFormat::Ddt, signature });
+        }
 
         let mut usage = [0u8; 1];
         reader.read_exact (&mut usage)?;
@@ -161,9 +177,12 @@ impl DdtFile {
         let images_per_level: u32 = if usage & DDT_USAGE_CUBE == DDT_USAGE_CUBE {6} else {1};
 
         for i in 0..(mipmap_levels as u32) * images_per_level {
+            if 16 + 8 * (i as u64 + 1) > data.len() as u64 {
+                return Err(ResourceError::CorruptedData(FileFormat::Ddt));
+            }
             reader.seek(SeekFrom::Start(16 + 8 * (i as u64)))?;
-            let width = cmp::max(1, base_width >> (i / (images_per_level as u32)));
-            let height = cmp::max(1, base_height >> (i / (images_per_level as u32)));
+            let width = cmp::max(1, base_width.checked_shr(i / (images_per_level as u32)).unwrap_or(0));
+            let height = cmp::max(1, base_height.checked_shr(i / (images_per_level as u32)).unwrap_or(0));
 
             let mut offset = [0u8; 4];
             reader.read_exact (&mut offset)?;
@@ -172,6 +191,9 @@ impl DdtFile {
             let mut length = [0u8; 4];
             reader.read_exact (&mut length)?;
             let length: u32 = u32::from_le_bytes(length);    
+            if offset as u64 + length as u64 > data.len() as u64 {
+                return Err(ResourceError::CorruptedData(FileFormat::Ddt));
+            }
             
             reader.seek(SeekFrom::Start(offset as u64))?;
 
@@ -225,4 +247,32 @@ fn convert_tga_to_ddt() {
     let ddt_file = DdtFile::from_tga(PathBuf::from(TEST_TGA_PATH)).unwrap();
          
 }
- 
\ No newline at end of file
+
+#[test]
+fn read_broken_ddt() {
+    assert!(matches!(DdtFile::read(b"RTS3"), Err(ResourceError::TruncatedHeader(FileFormat::Ddt))));
+    assert!(matches!(DdtFile::read(&[0u8; 16]), Err(ResourceError::BadSignature { format: FileFormat::Ddt, .. })));
+
+    let mut data: Vec<u8> = BINARY_SIGNATURE_DDT.to_le_bytes().to_vec();
+    data.extend_from_slice(&[0, 0, DDT_FORMAT_BGRA, 1]);
+    data.extend_from_slice(&4u32.to_le_bytes());
+    data.extend_from_slice(&4u32.to_le_bytes());
+    // image data points past the end of file
+    data.extend_from_slice(&24u32.to_le_bytes());
+    data.extend_from_slice(&64u32.to_le_bytes());
+    assert!(matches!(DdtFile::read(&data), Err(ResourceError::CorruptedData(FileFormat::Ddt))));
+    data.extend_from_slice(&[0u8; 64]);
+    assert!(DdtFile::read(&data).is_ok());
+
+    // size from header overflows block count and can't be stored in tga
+    let mut data: Vec<u8> = BINARY_SIGNATURE_DDT.to_le_bytes().to_vec();
+    data.extend_from_slice(&[0, 0, DDT_FORMAT_DXT1, 1]);
+    data.extend_from_slice(&u32::MAX.to_le_bytes());
+    data.extend_from_slice(&4u32.to_le_bytes());
+    data.extend_from_slice(&24u32.to_le_bytes());
+    data.extend_from_slice(&8u32.to_le_bytes());
+    data.extend_from_slice(&[0u8; 8]);
+    let ddt_file = DdtFile::read(&data).unwrap();
+    assert!(matches!(ddt_file.to_tga(), Err(ResourceError::CorruptedData(FileFormat::Ddt))));
+    assert!(matches!(ddt_file.images[0].decompress(DDT_FORMAT_DXT1, 0), Err(ResourceError::CorruptedData(FileFormat::Ddt))));
+}
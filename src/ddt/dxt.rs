@@ -7,7 +7,7 @@ use crate::consts::DDT_FORMAT_DXT3;
 use std::io::Cursor;
 use std::io::BufReader;
 use std::io::Read;
-use std::error::Error;
+use crate::error::{ResourceError, FileFormat};
 use std::mem::swap;
 
 pub struct DxtImage {
@@ -32,10 +32,16 @@ impl DxtImage {
     }
     
     // decompress DXT data
-    pub fn decompress(&self, format: u8, usage:u8) -> Result<Vec<u8>, Box<dyn Error>> {
-        let block_count_x = (self.width + 3 ) / 4;
-        let block_count_y = (self.height + 3) / 4;
-        let mut image_data = vec![0u8; (self.width * self.height * 4) as usize];
+    pub fn decompress(&self, format: u8, usage:u8) -> Result<Vec<u8>, ResourceError> {
+        // u64, so sizes from broken header can't overflow. check before allocating decoded image
+        let block_count_x = (self.width as u64).div_ceil(4);
+        let block_count_y = (self.height as u64).div_ceil(4);
+        if block_count_x * block_count_y * DxtImage::encoded_bytes_per_block(format) as u64 > self.raw_data.len() as u64 {
+            return Err(ResourceError::CorruptedData(FileFormat::Ddt));
+        }
+        let block_count_x = block_count_x as u32;
+        let block_count_y = block_count_y as u32;
+        let mut image_data = vec![0u8; self.width as usize * self.height as usize * 4];
         let mut reader = BufReader::new(Cursor::new(&self.raw_data));
         for y in 0..block_count_y {
             for x in 0..block_count_x {
@@ -296,7 +302,11 @@ impl DxtImage {
     
     }
     
-    pub fn compress(data: &[u8], format: u8, usage: u8, width: u32, height: u32) -> Vec<u8>{
+    pub fn compress(data: &[u8], format: u8, usage: u8, width: u32, height: u32) -> Result<Vec<u8>, ResourceError>{
+        // encoder works with whole 4x4 blocks of bgra pixels
+        if width == 0 || height == 0 || !width.is_multiple_of(4) || !height.is_multiple_of(4) || data.len() as u64 != width as u64 * height as u64 * 4 {
+            return Err(ResourceError::InvalidImageSize { width, height, size: data.len() });
+        }
     
         let data = DxtImage::prepare_to_encoding(data, format, usage);
     
@@ -318,7 +328,7 @@ impl DxtImage {
             }
             res.append(&mut buf);
         }
-        res
+        Ok(res)
     }
     
     fn decoded_bytes_per_block(format: u8) -> usize {
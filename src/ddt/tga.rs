@@ -3,26 +3,27 @@ use crate::consts::TGA_SCREEN_ORIGIN_BIT_MASK;
 use crate::consts::TGA_UNCOMPRESSED_TRUE_COLOR;
 use crate::consts::DDT_FORMAT_GREY;
 
+use crate::error::ResourceError;
+
 use std::{
-    error::Error,
     path::{PathBuf},
     fs::{File},
     io::{Write, BufWriter, Read, BufReader},
 };
 
 pub struct TgaFile {
-    id_length: u8,
-    map_type: u8,
-    image_type: u8,
-    map_origin: u16,
-    map_length: u16,
-    map_entry_size: u8,
-    x_origin: u16,
-    y_origin: u16,
+    pub id_length: u8,
+    pub map_type: u8,
+    pub image_type: u8,
+    pub map_origin: u16,
+    pub map_length: u16,
+    pub map_entry_size: u8,
+    pub x_origin: u16,
+    pub y_origin: u16,
     pub image_width: u16,
     pub image_height: u16,
-    pixel_depth: u8,
-    image_desc: u8,
+    pub pixel_depth: u8,
+    pub image_desc: u8,
     pub raw_data: Vec<u8>,
     pub image_id: [u8; 4],
 }
@@ -62,8 +63,8 @@ impl TgaFile {
         };
     }
 
-    pub fn open(path: PathBuf) -> Result<TgaFile, Box<dyn Error>> {
-        let file = File::open(&path).expect("Файл не найден");
+    pub fn open(path: PathBuf) -> Result<TgaFile, ResourceError> {
+        let file = File::open(&path)?;
         let mut reader = BufReader::new(file);  
 
         let mut id_length = [0u8; 1];
@@ -119,20 +120,25 @@ impl TgaFile {
 
 
 
-        let file_name = &path.file_name().unwrap().to_str().unwrap().to_owned();
+        // ddt params are stored in file name: name.(usage,alpha,format,mipmap_levels).tga
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
         let splitted_name: Vec<&str> = file_name.split(".").collect();
         if splitted_name.len() != 3 {
-            panic!("что то пошло не так");
+            return Err(ResourceError::InvalidTgaName(path));
         }
         let splitted_params: Vec<&str> = splitted_name[1].split(|c| c == ',' || c == '(' || c == ')').collect();
         if splitted_params.len() != 6 {
-            panic!("что то пошло не так");
+            return Err(ResourceError::InvalidTgaName(path));
         }    
         
-        let usage: u8 = splitted_params[1].parse()?;
-        let alpha: u8 = splitted_params[2].parse()?;
-        let format: u8 = splitted_params[3].parse()?;
-        let mipmap_levels: u8 = splitted_params[4].parse()?;
+        let params: Vec<u8> = match splitted_params[1..5].iter().map(|param| param.parse()).collect() {
+            Ok(params) => params,
+            Err(_) => return Err(ResourceError::InvalidTgaName(path)),
+        };
+        let usage: u8 = params[0];
+        let alpha: u8 = params[1];
+        let format: u8 = params[2];
+        let mipmap_levels: u8 = params[3];
 
         return Ok(TgaFile {
             id_length: id_length, 
@@ -153,7 +159,7 @@ impl TgaFile {
         
     }
 
-    fn to_bytes(&self) -> Vec<u8> {
+    pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes: Vec<u8> = Vec::new();
 
         bytes.extend_from_slice(&self.id_length.to_le_bytes());
@@ -172,9 +178,9 @@ impl TgaFile {
         return bytes;
     }
 
-    pub fn save(&self, mut path: PathBuf) -> Result<(), Box<dyn Error>> {
+    pub fn save(&self, mut path: PathBuf) -> Result<(), ResourceError> {
         path.set_extension("");
-        let file_name = path.file_name().unwrap().to_str().unwrap().to_owned();
+        let file_name = path.file_name().ok_or_else(|| ResourceError::InvalidPath(path.clone()))?.to_string_lossy().into_owned();
         path.set_file_name(file_name + ".(" + &self.image_id[0].to_string() + "," + &self.image_id[1].to_string() + "," + &self.image_id[2].to_string() + "," + &self.image_id[3].to_string() + ").tga");
         let mut writer = BufWriter::new(File::create(&path)?);
         writer.write(&self.to_bytes())?;
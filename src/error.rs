@@ -0,0 +1,175 @@
+use crate::loc::ERR_NOT_VALID_DECODED_WAV_SIGNATURE;
+use crate::loc::ERR_NOT_VALID_DDT_FORMAT;
+use crate::loc::ERR_BAR_NOT_FOUND;
+use crate::loc::ERR_NOT_VALID_DDT_SIGNATURE;
+use crate::loc::ERR_NOT_VALID_BAR_SIGNATURE;
+use crate::loc::ERR_NOT_SUPPORTED_BAR_VERSION;
+use crate::loc::ERR_NOT_VALID_BAR_MAGIC;
+use crate::loc::ERR_NOT_MATCHED_ENTRY_COUNT;
+use crate::loc::ERR_NOT_VALID_ALZ4_SIGNATURE;
+use crate::loc::ERR_NOT_VALID_ALZ4_DATA;
+use crate::loc::ERR_NOT_VALID_L33T_SIGNATURE;
+use crate::loc::ERR_NOT_VALID_L33T_DATA;
+use crate::loc::ERR_NOT_VALID_XMB_SIGNATURE;
+use crate::loc::ERR_NOT_VALID_XMB_DATA;
+use crate::loc::ERR_NOT_VALID_DATA;
+use crate::loc::ERR_TRUNCATED_HEADER;
+use crate::loc::ERR_TRUNCATED_FILES_TABLE;
+use crate::loc::ERR_OFFSET_OUT_OF_RANGE;
+use crate::loc::ERR_OFFSET_TOO_LARGE;
+use crate::loc::ERR_NOT_VALID_ENTRY_NAME;
+use crate::loc::ERR_NOT_VALID_TGA_NAME;
+use crate::loc::ERR_NOT_VALID_IMAGE_SIZE;
+use crate::loc::ERR_NOT_VALID_PATH;
+use crate::loc::ERR_NOT_VALID_XML;
+use crate::loc::ERR_NOT_VALID_PATTERN;
+use crate::loc::ERR_IN_ENTRY;
+use crate::loc::ERR_ENTRY_NOT_FOUND;
+use crate::loc::ERR_ENTRY_EXISTS;
+use crate::loc::ERR_UNSAFE_ENTRY_PATH;
+
+use std::{
+    error::Error,
+    fmt,
+    io,
+    path::PathBuf,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileFormat {
+    Bar,
+    Ddt,
+    Wav,
+    Alz4,
+    L33t,
+    Xmb,
+}
+
+impl fmt::Display for FileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FileFormat::Bar => "BAR",
+            FileFormat::Ddt => "DDT",
+            FileFormat::Wav => "WAV",
+            FileFormat::Alz4 => "alz4",
+            FileFormat::L33t => "l33t",
+            FileFormat::Xmb => "XMB",
+        };
+        f.write_str(name)
+    }
+}
+
+// errors of reading, decoding and writing resources. malformed input never panics
+#[derive(Debug)]
+pub enum ResourceError {
+    Io(io::Error),
+    BarNotFound(PathBuf),
+    BadSignature { format: FileFormat, signature: u32 },
+    BadMagic(u32),
+    UnsupportedVersion(u32),
+    EntryCountMismatch { file_count: u32, root_file_count: u32 },
+    TruncatedHeader(FileFormat),
+    TruncatedTable { offset: u64, index: u32 }, // offset of files table, index of entry being read
+    OffsetOutOfRange { offset: u64, size: u64, file_size: u64 },
+    OffsetTooLarge(u64), // offset of entry or files table doesn't fit in u32 of legacy archive
+    CorruptedData(FileFormat),
+    UnsupportedDdtFormat(u8),
+    InvalidImageSize { width: u32, height: u32, size: usize },
+    InvalidEntryName,
+    InvalidTgaName(PathBuf),
+    InvalidPath(PathBuf),
+    InvalidXml(String),
+    InvalidPattern(String),
+    EntryNotFound(String),
+    EntryExists(String),
+    UnsafeEntryPath(String), // relative parent, absolute or drive path in entry name
+    Entry { name: String, offset: u64, source: Box<ResourceError> },
+}
+
+impl ResourceError {
+    // add name and offset of bar entry to error
+    pub fn in_entry(self, name: String, offset: u64) -> ResourceError {
+        ResourceError::Entry { name, offset, source: Box::new(self) }
+    }
+}
+
+impl fmt::Display for ResourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResourceError::Io(error) => write!(f, "{}", error),
+            ResourceError::BarNotFound(path) => write!(f, "{} ({})", ERR_BAR_NOT_FOUND, path.display()),
+            ResourceError::BadSignature { format, signature } => {
+                let message = match format {
+                    FileFormat::Bar => ERR_NOT_VALID_BAR_SIGNATURE,
+                    FileFormat::Ddt => ERR_NOT_VALID_DDT_SIGNATURE,
+                    FileFormat::Wav => ERR_NOT_VALID_DECODED_WAV_SIGNATURE,
+                    FileFormat::Alz4 => ERR_NOT_VALID_ALZ4_SIGNATURE,
+                    FileFormat::L33t => ERR_NOT_VALID_L33T_SIGNATURE,
+                    FileFormat::Xmb => ERR_NOT_VALID_XMB_SIGNATURE,
+                };
+                write!(f, "{} (0x{:08X})", message, signature)
+            },
+            ResourceError::BadMagic(magic) => write!(f, "{} (0x{:08X})", ERR_NOT_VALID_BAR_MAGIC, magic),
+            ResourceError::UnsupportedVersion(version) => write!(f, "{} ({})", ERR_NOT_SUPPORTED_BAR_VERSION, version),
+            ResourceError::EntryCountMismatch { file_count, root_file_count } => {
+                write!(f, "{} ({} != {})", ERR_NOT_MATCHED_ENTRY_COUNT, file_count, root_file_count)
+            },
+            ResourceError::TruncatedHeader(format) => write!(f, "{} {}", ERR_TRUNCATED_HEADER, format),
+            ResourceError::TruncatedTable { offset, index } => {
+                write!(f, "{} (0x{:X}, #{})", ERR_TRUNCATED_FILES_TABLE, offset, index + 1)
+            },
+            ResourceError::OffsetOutOfRange { offset, size, file_size } => {
+                write!(f, "{} (0x{:X} + {} > {})", ERR_OFFSET_OUT_OF_RANGE, offset, size, file_size)
+            },
+            ResourceError::OffsetTooLarge(offset) => write!(f, "{} (0x{:X})", ERR_OFFSET_TOO_LARGE, offset),
+            ResourceError::CorruptedData(format) => match format {
+                FileFormat::Alz4 => write!(f, "{}", ERR_NOT_VALID_ALZ4_DATA),
+                FileFormat::L33t => write!(f, "{}", ERR_NOT_VALID_L33T_DATA),
+                FileFormat::Xmb => write!(f, "{}", ERR_NOT_VALID_XMB_DATA),
+                _ => write!(f, "{} {}", ERR_NOT_VALID_DATA, format),
+            },
+            ResourceError::UnsupportedDdtFormat(format) => write!(f, "{} ({})", ERR_NOT_VALID_DDT_FORMAT, format),
+            ResourceError::InvalidImageSize { width, height, size } => {
+                write!(f, "{} ({}x{}, {})", ERR_NOT_VALID_IMAGE_SIZE, width, height, size)
+            },
+            ResourceError::InvalidEntryName => write!(f, "{}", ERR_NOT_VALID_ENTRY_NAME),
+            ResourceError::InvalidTgaName(path) => write!(f, "{} ({})", ERR_NOT_VALID_TGA_NAME, path.display()),
+            ResourceError::InvalidPath(path) => write!(f, "{} ({})", ERR_NOT_VALID_PATH, path.display()),
+            ResourceError::InvalidXml(message) => write!(f, "{} {}", ERR_NOT_VALID_XML, message),
+            ResourceError::InvalidPattern(message) => write!(f, "{} {}", ERR_NOT_VALID_PATTERN, message),
+            ResourceError::EntryNotFound(name) => write!(f, "{} {}", ERR_ENTRY_NOT_FOUND, name),
+            ResourceError::EntryExists(name) => write!(f, "{} {}", ERR_ENTRY_EXISTS, name),
+            ResourceError::UnsafeEntryPath(name) => write!(f, "{} {}", ERR_UNSAFE_ENTRY_PATH, name),
+            ResourceError::Entry { name, offset, source } => write!(f, "{} {} (0x{:X}): {}", ERR_IN_ENTRY, name, offset, source),
+        }
+    }
+}
+
+impl Error for ResourceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ResourceError::Io(error) => Some(error),
+            ResourceError::Entry { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ResourceError {
+    fn from(error: io::Error) -> ResourceError {
+        ResourceError::Io(error)
+    }
+}
+
+#[test]
+fn describe_entry_error() {
+    let error = ResourceError::BadSignature { format: FileFormat::Ddt, signature: 0x12345678 }.in_entry("art\\icon.ddt".to_owned(), 0x130);
+    match &error {
+        ResourceError::Entry { source, .. } => assert!(matches!(**source, ResourceError::BadSignature { format: FileFormat::Ddt, .. })),
+        _ => panic!("entry context is lost"),
+    }
+    let message = error.to_string();
+    assert!(message.contains("art\\icon.ddt"));
+    assert!(message.contains("0x130"));
+    assert!(message.contains("0x12345678"));
+}
@@ -0,0 +1,147 @@
+use crate::error::ResourceError;
+
+use regex::{Regex, RegexBuilder};
+
+// normalize entry path the way the game resolves it: case-insensitive, backslash separated
+pub fn normalize_entry_path(path: &str) -> String {
+    path.replace('/', "\\").to_lowercase()
+}
+
+// match glob pattern with *, ** and ? wildcards
+fn glob_match_chars(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => {
+            if pattern.get(1) == Some(&'*') {
+                // ** matches across separators, **\ also matches zero directories
+                let rest = &pattern[2..];
+                if rest.first() == Some(&'\\') && glob_match_chars(&rest[1..], name) {
+                    return true;
+                }
+                (0..=name.len()).any(|i| glob_match_chars(rest, &name[i..]))
+            }
+            else {
+                for i in 0..=name.len() {
+                    if glob_match_chars(&pattern[1..], &name[i..]) {
+                        return true;
+                    }
+                    if i < name.len() && name[i] == '\\' {
+                        break;
+                    }
+                }
+                false
+            }
+        },
+        Some('?') => !name.is_empty() && name[0] != '\\' && glob_match_chars(&pattern[1..], &name[1..]),
+        Some(c) => !name.is_empty() && name[0] == *c && glob_match_chars(&pattern[1..], &name[1..]),
+    }
+}
+
+// match entry path against glob pattern or extension (".xmb").
+// patterns without separators are matched against file name only
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let mut pattern = normalize_entry_path(pattern);
+    let path = normalize_entry_path(path);
+    if pattern.starts_with('.') && !pattern.contains(['*', '?', '\\']) {
+        pattern = format!("*{}", pattern);
+    }
+    let name = if pattern.contains('\\') {
+        path.as_str()
+    }
+    else {
+        path.rsplit('\\').next().unwrap_or("")
+    };
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    glob_match_chars(&pattern, &name)
+}
+
+pub fn glob_match_any(patterns: &[String], path: &str) -> bool {
+    patterns.iter().any(|pattern| glob_match(pattern, path))
+}
+
+
+enum Pattern {
+    Glob(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn matches(&self, path: &str) -> bool {
+        match self {
+            Pattern::Glob(pattern) => glob_match(pattern, path),
+            Pattern::Regex(regex) => regex.is_match(&path.replace('/', "\\")),
+        }
+    }
+}
+
+// include and exclude patterns for entry names. empty include list matches everything
+#[derive(Default)]
+pub struct EntryFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl EntryFilter {
+    pub fn new() -> EntryFilter {
+        EntryFilter::default()
+    }
+
+    fn regex(pattern: &str) -> Result<Regex, ResourceError> {
+        RegexBuilder::new(pattern).case_insensitive(true).build().map_err(|error| ResourceError::InvalidPattern(error.to_string()))
+    }
+
+    pub fn include_glob(&mut self, pattern: &str) {
+        self.include.push(Pattern::Glob(pattern.to_owned()));
+    }
+
+    pub fn exclude_glob(&mut self, pattern: &str) {
+        self.exclude.push(Pattern::Glob(pattern.to_owned()));
+    }
+
+    pub fn include_regex(&mut self, pattern: &str) -> Result<(), ResourceError> {
+        self.include.push(Pattern::Regex(EntryFilter::regex(pattern)?));
+        Ok(())
+    }
+
+    pub fn exclude_regex(&mut self, pattern: &str) -> Result<(), ResourceError> {
+        self.exclude.push(Pattern::Regex(EntryFilter::regex(pattern)?));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches(path)))
+            && !self.exclude.iter().any(|pattern| pattern.matches(path))
+    }
+}
+
+#[test]
+fn match_entry_globs() {
+    assert!(glob_match(".xmb", "data\\proto.xml.XMB"));
+    assert!(glob_match("*.xmb", "data/proto.xml.xmb"));
+    assert!(!glob_match(".xmb", "data\\proto.xml"));
+    assert!(glob_match("art\\units\\**\\*.ddt", "Art\\Units\\Infantry\\Musketeer\\musketeer.ddt"));
+    assert!(glob_match("art\\units\\**\\*.ddt", "art\\units\\icon.ddt"));
+    assert!(!glob_match("art\\units\\*.ddt", "art\\units\\infantry\\icon.ddt"));
+    assert!(glob_match("data\\?roto.xml", "data\\proto.xml"));
+}
+
+#[test]
+fn match_entry_filter() {
+    let mut filter = EntryFilter::new();
+    assert!(filter.matches("data\\proto.xml"));
+    filter.include_glob("art\\units\\**\\*.ddt");
+    filter.include_regex(r"^data\\.*\.xml\.xmb$").unwrap();
+    filter.exclude_glob("*_bump.ddt");
+    filter.exclude_regex("techtree").unwrap();
+    assert!(filter.matches("Art\\Units\\Infantry\\musketeer.ddt"));
+    assert!(!filter.matches("art\\units\\infantry\\musketeer_bump.ddt"));
+    assert!(filter.matches("Data/Proto.xml.XMB"));
+    assert!(!filter.matches("data\\techtree.xml.xmb"));
+    assert!(!filter.matches("sound\\attack.wav"));
+    assert!(EntryFilter::new().include_regex("(").is_err());
+}
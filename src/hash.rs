@@ -0,0 +1,288 @@
+use crate::filter;
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+// content hashes of entry data: xxh64 for quick comparison, sha-256 for manifests that must not collide
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ContentHash {
+    pub size: u64,
+    pub xxh64: u64,
+    pub sha256: [u8; 32],
+}
+
+// hashes of stored (possibly compressed or encoded) and decoded bytes of bar entry
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryHash {
+    pub name: String,
+    pub stored: ContentHash,
+    pub decoded: ContentHash,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HashDifference {
+    Added(String),
+    Removed(String),
+    Changed(String),
+}
+
+const XXH_PRIME64_1: u64 = 0x9E3779B185EBCA87;
+const XXH_PRIME64_2: u64 = 0xC2B2AE3D27D4EB4F;
+const XXH_PRIME64_3: u64 = 0x165667B19E3779F9;
+const XXH_PRIME64_4: u64 = 0x85EBCA77C2B2AE63;
+const XXH_PRIME64_5: u64 = 0x27D4EB2F165667C5;
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn xxh64_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(XXH_PRIME64_2)).rotate_left(31).wrapping_mul(XXH_PRIME64_1)
+}
+
+fn xxh64_merge_round(acc: u64, value: u64) -> u64 {
+    (acc ^ xxh64_round(0, value)).wrapping_mul(XXH_PRIME64_1).wrapping_add(XXH_PRIME64_4)
+}
+
+// xxHash64 with zero seed
+pub fn xxh64(data: &[u8]) -> u64 {
+    let mut pos: usize = 0;
+    let mut hash: u64;
+    if data.len() >= 32 {
+        let mut v1 = XXH_PRIME64_1.wrapping_add(XXH_PRIME64_2);
+        let mut v2 = XXH_PRIME64_2;
+        let mut v3: u64 = 0;
+        let mut v4 = XXH_PRIME64_1.wrapping_neg();
+        while pos + 32 <= data.len() {
+            v1 = xxh64_round(v1, read_u64(data, pos));
+            v2 = xxh64_round(v2, read_u64(data, pos + 8));
+            v3 = xxh64_round(v3, read_u64(data, pos + 16));
+            v4 = xxh64_round(v4, read_u64(data, pos + 24));
+            pos += 32;
+        }
+        hash = v1.rotate_left(1).wrapping_add(v2.rotate_left(7)).wrapping_add(v3.rotate_left(12)).wrapping_add(v4.rotate_left(18));
+        hash = xxh64_merge_round(hash, v1);
+        hash = xxh64_merge_round(hash, v2);
+        hash = xxh64_merge_round(hash, v3);
+        hash = xxh64_merge_round(hash, v4);
+    }
+    else {
+        hash = XXH_PRIME64_5;
+    }
+    hash = hash.wrapping_add(data.len() as u64);
+
+    while pos + 8 <= data.len() {
+        hash ^= xxh64_round(0, read_u64(data, pos));
+        hash = hash.rotate_left(27).wrapping_mul(XXH_PRIME64_1).wrapping_add(XXH_PRIME64_4);
+        pos += 8;
+    }
+    if pos + 4 <= data.len() {
+        let value = u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as u64;
+        hash ^= value.wrapping_mul(XXH_PRIME64_1);
+        hash = hash.rotate_left(23).wrapping_mul(XXH_PRIME64_2).wrapping_add(XXH_PRIME64_3);
+        pos += 4;
+    }
+    while pos < data.len() {
+        hash ^= (data[pos] as u64).wrapping_mul(XXH_PRIME64_5);
+        hash = hash.rotate_left(11).wrapping_mul(XXH_PRIME64_1);
+        pos += 1;
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(XXH_PRIME64_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(XXH_PRIME64_3);
+    hash ^= hash >> 32;
+    hash
+}
+
+fn sha256_block(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for i in 0..16 {
+        w[i] = u32::from_be_bytes([block[4 * i], block[4 * i + 1], block[4 * i + 2], block[4 * i + 3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let temp1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(SHA256_K[i]).wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let temp2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(temp1);
+        d = c;
+        c = b;
+        b = a;
+        a = temp1.wrapping_add(temp2);
+    }
+    for (value, added) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *value = value.wrapping_add(added);
+    }
+}
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+    let mut blocks = data.chunks_exact(64);
+    for block in &mut blocks {
+        sha256_block(&mut state, block);
+    }
+
+    // last bytes, 0x80 and length in bits fill one or two blocks
+    let mut tail: Vec<u8> = blocks.remainder().to_vec();
+    tail.push(0x80);
+    while tail.len() % 64 != 56 {
+        tail.push(0);
+    }
+    tail.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_be_bytes());
+    for block in tail.chunks_exact(64) {
+        sha256_block(&mut state, block);
+    }
+
+    let mut digest = [0u8; 32];
+    for (i, value) in state.iter().enumerate() {
+        digest[4 * i..4 * i + 4].copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 == 1 {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+impl ContentHash {
+    pub fn new(data: &[u8]) -> ContentHash {
+        ContentHash {
+            size: data.len() as u64,
+            xxh64: xxh64(data),
+            sha256: sha256(data),
+        }
+    }
+}
+
+impl EntryHash {
+    pub const MANIFEST_HEADER: &'static str = "#name\tstored_size\tstored_xxh64\tstored_sha256\tdecoded_size\tdecoded_xxh64\tdecoded_sha256";
+
+    // tab separated manifest line, same order as MANIFEST_HEADER
+    pub fn to_manifest_line(&self) -> String {
+        format!("{}\t{}\t{:016x}\t{}\t{}\t{:016x}\t{}", self.name,
+            self.stored.size, self.stored.xxh64, to_hex(&self.stored.sha256),
+            self.decoded.size, self.decoded.xxh64, to_hex(&self.decoded.sha256))
+    }
+
+    // None for comments and malformed lines
+    pub fn from_manifest_line(line: &str) -> Option<EntryHash> {
+        if line.starts_with('#') {
+            return None;
+        }
+        let fields: Vec<&str> = line.trim_end_matches(['\r', '\n']).split('\t').collect();
+        if fields.len() != 7 {
+            return None;
+        }
+        let content_hash = |size: &str, xxh64: &str, sha256: &str| -> Option<ContentHash> {
+            Some(ContentHash {
+                size: size.parse().ok()?,
+                xxh64: u64::from_str_radix(xxh64, 16).ok()?,
+                sha256: from_hex(sha256)?.try_into().ok()?,
+            })
+        };
+        Some(EntryHash {
+            name: fields[0].to_owned(),
+            stored: content_hash(fields[1], fields[2], fields[3])?,
+            decoded: content_hash(fields[4], fields[5], fields[6])?,
+        })
+    }
+}
+
+pub fn to_manifest(hashes: &[EntryHash]) -> String {
+    let mut manifest = String::from(EntryHash::MANIFEST_HEADER);
+    manifest.push('\n');
+    for hash in hashes {
+        manifest.push_str(&hash.to_manifest_line());
+        manifest.push('\n');
+    }
+    manifest
+}
+
+pub fn read_manifest(manifest: &str) -> Vec<EntryHash> {
+    manifest.lines().filter_map(EntryHash::from_manifest_line).collect()
+}
+
+// entries are matched by name (case-insensitive) and compared by decoded content,
+// so recompressed entries with same content are not reported
+pub fn compare(old: &[EntryHash], new: &[EntryHash]) -> Vec<HashDifference> {
+    let old_hashes: HashMap<String, &EntryHash> = old.iter().map(|hash| (filter::normalize_entry_path(&hash.name), hash)).collect();
+    let new_hashes: HashMap<String, &EntryHash> = new.iter().map(|hash| (filter::normalize_entry_path(&hash.name), hash)).collect();
+    let mut differences: Vec<HashDifference> = Vec::new();
+    for hash in new {
+        match old_hashes.get(&filter::normalize_entry_path(&hash.name)) {
+            None => differences.push(HashDifference::Added(hash.name.clone())),
+            Some(old_hash) if old_hash.decoded != hash.decoded => differences.push(HashDifference::Changed(hash.name.clone())),
+            Some(_) => (),
+        }
+    }
+    for hash in old {
+        if !new_hashes.contains_key(&filter::normalize_entry_path(&hash.name)) {
+            differences.push(HashDifference::Removed(hash.name.clone()));
+        }
+    }
+    differences
+}
+
+
+#[test]
+fn hash_known_values() {
+    assert_eq!(xxh64(b""), 0xEF46DB3751D8E999);
+    assert_eq!(xxh64(b"abc"), 0x44BC2CF5AD770999);
+    assert_eq!(xxh64(b"Nobody inspects the spammish repetition"), 0xFBCEA83C8A378BF1);
+    assert_eq!(to_hex(&sha256(b"")), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(to_hex(&sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    assert_eq!(to_hex(&sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")), "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+}
+
+#[test]
+fn compare_manifests() {
+    let entry_hash = |name: &str, data: &[u8]| EntryHash { name: name.to_owned(), stored: ContentHash::new(data), decoded: ContentHash::new(data) };
+    let old = vec![entry_hash("data\\proto.xml", b"<proto/>"), entry_hash("art\\icon.ddt", b"RTS3"), entry_hash("data\\techtree.xml", b"<techtree/>")];
+    let new = vec![entry_hash("Data\\Proto.xml", b"<proto/>"), entry_hash("data\\techtree.xml", b"<techtree></techtree>"), entry_hash("sound\\attack.wav", b"RIFF")];
+
+    let manifest = to_manifest(&old);
+    assert!(manifest.starts_with(EntryHash::MANIFEST_HEADER));
+    assert_eq!(read_manifest(&manifest), old);
+
+    assert_eq!(compare(&old, &new), vec![
+        HashDifference::Changed("data\\techtree.xml".to_owned()),
+        HashDifference::Added("sound\\attack.wav".to_owned()),
+        HashDifference::Removed("art\\icon.ddt".to_owned()),
+    ]);
+}
@@ -0,0 +1,76 @@
+use crate::consts::BINARY_SIGNATURE_L33T;
+
+use crate::error::{ResourceError, FileFormat};
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::{
+    cmp,
+    io::{Read, Write},
+};
+
+// l33t header: signature, uncompressed size
+pub const L33T_HEADER_SIZE: usize = 8;
+
+// size of data after decompression, taken from l33t header
+pub fn uncompressed_size(source: &[u8]) -> Result<u32, ResourceError> {
+    let header = source.get(..L33T_HEADER_SIZE).ok_or(ResourceError::CorruptedData(FileFormat::L33t))?;
+    let signature = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    if signature != BINARY_SIGNATURE_L33T {
+        return Err(ResourceError::BadSignature { format: FileFormat::L33t, signature });
+    }
+    Ok(u32::from_le_bytes([header[4], header[5], header[6], header[7]]))
+}
+
+// decompress l33t wrapped data (header + zlib stream)
+pub fn decompress(source: &[u8]) -> Result<Vec<u8>, ResourceError> {
+    let size = uncompressed_size(source)? as usize;
+    // size comes from header, so don't trust it more than zlib ratio allows
+    let mut data: Vec<u8> = Vec::with_capacity(cmp::min(size, source.len().saturating_mul(1032)));
+    // read one byte more than header says, so longer stream is detected without decompressing all of it
+    let mut decoder = ZlibDecoder::new(&source[L33T_HEADER_SIZE..]).take(size as u64 + 1);
+    decoder.read_to_end(&mut data).map_err(|_| ResourceError::CorruptedData(FileFormat::L33t))?;
+    if data.len() != size {
+        return Err(ResourceError::CorruptedData(FileFormat::L33t));
+    }
+    Ok(data)
+}
+
+
+// compress data to l33t wrapped zlib stream
+pub fn compress(source: &[u8]) -> Result<Vec<u8>, ResourceError> {
+    let mut dest: Vec<u8> = Vec::with_capacity(L33T_HEADER_SIZE + source.len() / 2);
+    dest.extend_from_slice(&BINARY_SIGNATURE_L33T.to_le_bytes());
+    dest.extend_from_slice(&(source.len() as u32).to_le_bytes());
+    let mut encoder = ZlibEncoder::new(dest, Compression::default());
+    encoder.write_all(source)?;
+    Ok(encoder.finish()?)
+}
+
+#[test]
+fn decompress_l33t_data() {
+    // zlib stream of "l33t l33t l33t"
+    let stream: Vec<u8> = vec![
+        0x78, 0x9C, 0xCB, 0x31, 0x36, 0x2E, 0x51, 0xC8,
+        0x81, 0x11, 0x00, 0x1E, 0x71, 0x04, 0x13,
+    ];
+    let mut data: Vec<u8> = Vec::new();
+    data.extend_from_slice(&BINARY_SIGNATURE_L33T.to_le_bytes());
+    data.extend_from_slice(&14u32.to_le_bytes());
+    data.extend_from_slice(&stream);
+    assert_eq!(decompress(&data).unwrap(), b"l33t l33t l33t");
+
+    // stream is longer than header says
+    data[4..8].copy_from_slice(&4u32.to_le_bytes());
+    assert!(matches!(decompress(&data), Err(ResourceError::CorruptedData(FileFormat::L33t))));
+}
+
+#[test]
+fn compress_l33t_data() {
+    let source: Vec<u8> = b"<unit>Musketeer</unit>".repeat(1000);
+    let data = compress(&source).unwrap();
+    assert!(data.len() < source.len());
+    assert_eq!(uncompressed_size(&data).unwrap() as usize, source.len());
+    assert_eq!(decompress(&data).unwrap(), source);
+}
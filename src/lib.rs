@@ -0,0 +1,25 @@
+// resource manager library: reading and writing Age of Empires III resources.
+// BAR archives, DDT textures, XMB documents, alz4/l33t compression, sound encoding and content hashes
+pub mod bar;
+pub mod ddt;
+pub mod sound;
+pub mod alz4;
+pub mod l33t;
+pub mod xmb;
+pub mod filter;
+pub mod datetime;
+pub mod hash;
+pub mod convert;
+pub mod error;
+pub mod loc;
+pub mod consts;
+
+pub use crate::bar::{BarFile, BarEntry, EntryReader};
+pub use crate::ddt::DdtFile;
+pub use crate::ddt::tga::TgaFile;
+pub use crate::ddt::dxt::DxtImage;
+pub use crate::xmb::XmbFile;
+pub use crate::filter::EntryFilter;
+pub use crate::hash::EntryHash;
+pub use crate::convert::convert_file;
+pub use crate::error::{ResourceError, FileFormat};
@@ -9,3 +9,35 @@ pub const ERR_NOT_VALID_BAR_SIGNATURE: &str = "Ошибка при чтении:
 pub const ERR_NOT_SUPPORTED_BAR_VERSION: &str = "Ошибка при чтении: текущая версия BAR файла не поддерживается. Обратитесь к разработчику."; //"Version {} of the BAR file is not supported. Please contact the developer"
 pub const ERR_NOT_VALID_BAR_MAGIC: &str = "Ошибка при чтении: неправильный magic BAR файла."; //"File is not a valid BAR file. Bad magic number 1"
 pub const ERR_NOT_MATCHED_ENTRY_COUNT: &str = "Ошибка при чтении: несовпадают поля file_count и root_file_count."; //"File count does not match root file count";
+
+pub const ERR_NOT_VALID_ALZ4_SIGNATURE: &str = "Ошибка при декодировании: неверная сигнатура alz4 файла."; //File is not a valid alz4 file. Wrong header.
+pub const ERR_NOT_VALID_ALZ4_DATA: &str = "Ошибка при декодировании: повреждены сжатые данные alz4."; //alz4 compressed data is corrupted.
+pub const ERR_NOT_VALID_L33T_SIGNATURE: &str = "Ошибка при декодировании: неверная сигнатура l33t файла."; //File is not a valid l33t file. Wrong header.
+pub const ERR_NOT_VALID_L33T_DATA: &str = "Ошибка при декодировании: повреждены сжатые данные l33t."; //l33t compressed data is corrupted.
+pub const ERR_NOT_VALID_XMB_SIGNATURE: &str = "Ошибка при декодировании: неверная сигнатура XMB файла."; //File is not a valid XMB file. Wrong header.
+pub const ERR_NOT_VALID_XMB_DATA: &str = "Ошибка при декодировании: повреждены данные XMB файла."; //XMB data is corrupted.
+pub const ERR_UNKNOWN_OPTION: &str = "Ошибка в аргументах: неизвестный параметр"; //Unknown option
+pub const ERR_NOT_VALID_OPTION_VALUE: &str = "Ошибка в аргументах: неверное или отсутствующее значение параметра"; //Missing or invalid option value
+pub const ERR_NOT_SUPPORTED_FILE: &str = "Ошибка при конвертации: формат файла не поддерживается."; //File format is not supported
+pub const ERR_BROKEN_ENTRIES: &str = "Ошибка при проверке: BAR файл содержит поврежденные записи."; //BAR file contains broken entries
+pub const ERR_FILE_MANAGER_NOT_STARTED: &str = "Предупреждение: не удалось открыть папку через"; //Warning: could not open folder with
+pub const ERR_NOT_VALID_DATA: &str = "Ошибка при декодировании: повреждены данные файла"; //Data is corrupted in file of format
+pub const ERR_TRUNCATED_HEADER: &str = "Ошибка при чтении: обрезан заголовок файла"; //Header is truncated in file of format
+pub const ERR_TRUNCATED_FILES_TABLE: &str = "Ошибка при чтении: таблица файлов BAR обрезана."; //BAR files table is truncated
+pub const ERR_OFFSET_OUT_OF_RANGE: &str = "Ошибка при чтении: смещение выходит за пределы файла."; //Offset is out of file range
+pub const ERR_OFFSET_TOO_LARGE: &str = "Ошибка при записи: смещение больше 4 ГБ не помещается в legacy BAR файл."; //Offset above 4 GiB does not fit in legacy BAR file
+pub const ERR_NOT_VALID_ENTRY_NAME: &str = "Ошибка при чтении: неверное имя записи (UTF-16)."; //Entry name is not valid UTF-16
+pub const ERR_NOT_VALID_TGA_NAME: &str = "Ошибка при чтении: имя TGA файла должно иметь вид name.(usage,alpha,format,mipmaps).tga"; //TGA file name must look like name.(usage,alpha,format,mipmaps).tga
+pub const ERR_NOT_VALID_IMAGE_SIZE: &str = "Ошибка при кодировании: размер изображения не подходит для DXT сжатия."; //Image size is not suitable for DXT compression
+pub const ERR_NOT_VALID_PATH: &str = "Ошибка в аргументах: неверный путь."; //Invalid path
+pub const ERR_NOT_VALID_XML: &str = "Ошибка при чтении XML:"; //XML parsing error:
+pub const ERR_NOT_VALID_PATTERN: &str = "Ошибка в аргументах: неверное регулярное выражение."; //Invalid regular expression
+pub const ERR_IN_ENTRY: &str = "Ошибка в записи"; //Error in entry
+pub const ERR_ENTRY_NOT_FOUND: &str = "Ошибка: запись не найдена в BAR файле:"; //Entry is not found in BAR file:
+pub const ERR_ENTRY_EXISTS: &str = "Ошибка: запись уже есть в BAR файле:"; //Entry already exists in BAR file:
+pub const ERR_UNSAFE_ENTRY_PATH: &str = "Ошибка при распаковке: путь записи выходит за пределы папки (используйте --trust-paths для доверенных архивов):"; //Entry path leads outside of output folder (use --trust-paths for trusted archives):
+pub const ERR_ENTRY_OUT_OF_DATA: &str = "Ошибка при проверке: запись выходит за пределы области данных BAR файла"; //Entry lies outside of BAR data area
+pub const ERR_ENTRY_OVERLAP: &str = "Ошибка при проверке: данные записи пересекаются с записью"; //Entry data overlaps with entry
+pub const ERR_EMPTY_ENTRY: &str = "Ошибка при проверке: запись имеет нулевой размер."; //Entry has zero length
+pub const ERR_DUPLICATE_ENTRY: &str = "Ошибка при проверке: имя записи повторяется."; //Duplicate entry name
+pub const ERR_ENCODING_MISMATCH: &str = "Ошибка при проверке: сигнатура данных не соответствует типу кодирования записи"; //Data signature does not match encoding type of entry
@@ -9,14 +9,11 @@
 // fast hash tool
 // code optimization
 // test for every shit
-use crate::consts::BAR_VERSION_AOE3DE;
-mod ddt;
+use resource_manager::consts::BAR_VERSION_AOE3DE;
+use resource_manager::consts::BAR_VERSION_AOE3;
+use resource_manager::convert;
 
-mod bar;
-mod loc;
-mod consts;
-
-use crate::bar::{
+use resource_manager::bar::{
     BarFile,
 };
 use std::{
@@ -29,28 +26,39 @@ use std::{
     error::Error,
     time::{Instant,SystemTime},
 };
-use crate::ddt::DdtFile;
+use resource_manager::datetime::DateTime;
+use resource_manager::filter::EntryFilter;
+use resource_manager::hash::{self, HashDifference};
 
-use crate::consts::BINARY_SIGNATURE_DDT;
-use crate::consts::BINARY_SIGNATURE_ALZ4;
-use crate::consts::BINARY_SIGNATURE_L33T;
+use resource_manager::consts::BINARY_SIGNATURE_BAR;
 
-use crate::consts::BINARY_SIGNATURE_WAV_ENCODED;
-use crate::consts::BINARY_SIGNATURE_BAR;
+use resource_manager::loc::ERR_UNKNOWN_OPTION;
+use resource_manager::loc::ERR_NOT_VALID_OPTION_VALUE;
+use resource_manager::loc::ERR_NOT_SUPPORTED_FILE;
+use resource_manager::loc::ERR_BROKEN_ENTRIES;
+use resource_manager::loc::ERR_FILE_MANAGER_NOT_STARTED;
 
 use std::io::BufReader;
 use std::io::stdin;
+use std::io::stdout;
+use std::io::IsTerminal;
+use std::io::ErrorKind;
 use std::fs::File;
 use std::io::Read;
-use std::io::BufWriter;
-use std::io::Seek;
-use std::io::Write;
-use std::io::SeekFrom;
 use std::path::PathBuf;
 
 fn print_help(){
     println!("Resource Manager Command Line Tool v.5.0, developed by © VladTheJunior, 2021");
-    println!("Uses given string as path argument and automatically checks it for action:");
+    println!("Usage: resource_manager <command> <path> [options]");
+    println!("Commands:");
+    println!("    {:<12} {}", "list", "List entries of BAR file.");
+    println!("    {:<12} {}", "extract", "Extract, decode and convert all entries of BAR file.");
+    println!("    {:<12} {}", "create", "Archive all items in directory to selected version of BAR file.");
+    println!("    {:<12} {}", "convert", "Decode/encode and convert single file (XMB, XML, DDT, WAV, alz4, l33t).");
+    println!("    {:<12} {}", "info", "Give info about BAR structure.");
+    println!("    {:<12} {}", "verify", "Check structure of BAR file (entry ranges, overlaps, names, encoding) and report broken entries.");
+    println!("    {:<12} {}", "hash", "Print manifest with xxh64 and SHA-256 of stored and decoded bytes of BAR entries.");
+    println!("Without command uses given string as path argument and automatically checks it for action:");
     println!("    {:<12} {}", "BAR file", "Extract, decode and convert all entries. Gives info about BAR structure and entries.");
     println!("    {:<12} {}", "Directory", "Archive all items in directory to selected version of BAR file.");
     println!("    {:<12} {}", "XMB file", "Decode and convert it to XML file.");
@@ -58,99 +66,354 @@ fn print_help(){
     println!("    {:<12} {}", "DDT file", "Decode and convert it to TGA and PNG file.");
     println!("    {:<12} {}", "TGA file", "Convert and decode it to DDT file.");
     println!("    {:<12} {}", "WAV file", "Encode/decode it to decoded/encoded WAV file.");
+    println!("Options:");
+    println!("    {:<14} {}", "--bar-version", "Version of created BAR file: de (default) or legacy.");
+    println!("    {:<14} {}", "--compress", "Glob pattern or extension (e.g. .xmb) of entries to compress (alz4 for DE, l33t for legacy) when creating BAR file. Can be repeated.");
+    println!("    {:<14} {}", "--filter", "Glob pattern or extension of entries to list or extract, e.g. art\\units\\**\\*.ddt. Can be repeated.");
+    println!("    {:<14} {}", "--regex", "Regular expression for entries to list or extract. Can be repeated.");
+    println!("    {:<14} {}", "--exclude", "Glob pattern or extension of entries to skip. Can be repeated.");
+    println!("    {:<14} {}", "--exclude-regex", "Regular expression for entries to skip. Can be repeated.");
+    println!("    {:<14} {}", "", "Entry names are matched case-insensitive, with either slash or backslash.");
+    println!("    {:<14} {}", "--alz4", "Compress XMB file converted from XML with alz4 (DE).");
+    println!("    {:<14} {}", "--threads", "Count of threads for BAR extraction (0 - all available cores).");
+    println!("    {:<14} {}", "--trust-paths", "Extract entry names as they are, even leading outside of output folder. Only for trusted BAR files.");
+    println!("    {:<14} {}", "--full", "Also read and decode every entry when verifying BAR file.");
+    println!("    {:<14} {}", "--compare", "BAR file or saved manifest to compare hashes with. Prints added (+), removed (-) and changed (*) entries.");
+    println!("    {:<14} {}", "--output", "Output folder. Default is managed folder in current directory.");
+    println!("    {:<14} {}", "--session", "Create separate timestamped folder in output folder for this run.");
+    println!("    {:<14} {}", "--headless", "Do not wait for key press and do not open output folder. Default when input is not a terminal.");
+    println!("    {:<14} {}", "--open", "Open output folder with system file manager, also in headless mode.");
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
+const COMMANDS: [&str; 7] = ["list", "extract", "create", "convert", "info", "verify", "hash"];
+// commands writing to managed folder
+const OUTPUT_COMMANDS: [&str; 3] = ["extract", "create", "convert"];
+
+struct Options {
+    bar_version: u32,
+    compress: Vec<String>,
+    filter: EntryFilter,
+    compress_xmb: bool,
+    threads: Option<usize>,
+    trust_paths: bool,
+    full_verify: bool,
+    compare: Option<PathBuf>,
+    headless: bool,
+    open: bool,
+    managed_path: PathBuf, // folder with extracted, converted and created subfolders
+}
 
 /*
     Managed file structure
 
     managed:
-    |---12:12:2021 12:44:21:
+    |---timing (or 2021-12-12_12-44-21-123 with --session):
         |---extracted
         |---converted
         |---created
 */
+// session folder is created only when command writes to managed folder
+fn parse_options(args: &[String], writes_output: bool) -> Result<Options, Box<dyn Error>> {
+    let mut output_path: Option<PathBuf> = None;
+    let mut session = false;
+    let mut options = Options {
+        bar_version: BAR_VERSION_AOE3DE,
+        compress: Vec::new(),
+        filter: EntryFilter::new(),
+        compress_xmb: false,
+        threads: None,
+        trust_paths: false,
+        full_verify: false,
+        compare: None,
+        headless: !stdin().is_terminal(),
+        open: false,
+        managed_path: PathBuf::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bar-version" => {
+                options.bar_version = match args.next().map(|value| value.to_lowercase()).as_deref() {
+                    Some("de") | Some("6") => BAR_VERSION_AOE3DE,
+                    Some("legacy") | Some("2") => BAR_VERSION_AOE3,
+                    _ => return Err(format!("{}: {}", ERR_NOT_VALID_OPTION_VALUE, arg).into()),
+                };
+            },
+            "--compress" => {
+                options.compress.push(args.next().ok_or(ERR_NOT_VALID_OPTION_VALUE)?.to_owned());
+            },
+            "--filter" => {
+                options.filter.include_glob(args.next().ok_or(ERR_NOT_VALID_OPTION_VALUE)?);
+            },
+            "--regex" => {
+                options.filter.include_regex(args.next().ok_or(ERR_NOT_VALID_OPTION_VALUE)?)?;
+            },
+            "--exclude" => {
+                options.filter.exclude_glob(args.next().ok_or(ERR_NOT_VALID_OPTION_VALUE)?);
+            },
+            "--exclude-regex" => {
+                options.filter.exclude_regex(args.next().ok_or(ERR_NOT_VALID_OPTION_VALUE)?)?;
+            },
+            "--alz4" => {
+                options.compress_xmb = true;
+            },
+            "--threads" => {
+                options.threads = Some(args.next().ok_or(ERR_NOT_VALID_OPTION_VALUE)?.parse()?);
+            },
+            "--trust-paths" => {
+                options.trust_paths = true;
+            },
+            "--full" => {
+                options.full_verify = true;
+            },
+            "--compare" => {
+                options.compare = Some(PathBuf::from(args.next().ok_or(ERR_NOT_VALID_OPTION_VALUE)?));
+            },
+            "--headless" => {
+                options.headless = true;
+            },
+            "--open" => {
+                options.open = true;
+            },
+            "--output" => {
+                output_path = Some(PathBuf::from(args.next().ok_or(ERR_NOT_VALID_OPTION_VALUE)?));
+            },
+            "--session" => {
+                session = true;
+            },
+            _ => return Err(format!("{}: {}", ERR_UNKNOWN_OPTION, arg).into()),
+        }
+    }
+    options.managed_path = match output_path {
+        _ if session && writes_output => {
+            create_session_folder(&output_path.unwrap_or(env::current_dir()?.join("managed")))?
+        },
+        Some(output_path) => output_path,
+        // default managed folder keeps results of last run in timing subfolder
+        None => env::current_dir()?.join("managed").join("timing"),
+    };
+    Ok(options)
+}
+
+// timestamped folder, so concurrent runs don't overwrite each other
+fn create_session_folder(output_path: &PathBuf) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(output_path)?;
+    let name = DateTime::from_system_time(SystemTime::now()).to_file_name();
+    let mut index = 0;
+    loop {
+        let session_path = match index {
+            0 => output_path.join(&name),
+            _ => output_path.join(format!("{}_{}", name, index)),
+        };
+        match fs::create_dir(&session_path) {
+            Ok(()) => return Ok(session_path),
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => index += 1,
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+fn read_signature(path: &PathBuf) -> Result<u32, Box<dyn Error>> {
+    let mut signature = [0u8; 4];
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+    if reader.read(&mut signature)? < 4 {
+        return Ok(0);
+    }
+    Ok(u32::from_le_bytes(signature))
+}
+
+fn list_bar(path: PathBuf, options: &Options) -> Result<(), Box<dyn Error>> {
+    let bar = BarFile::open(path)?;
+    for entry in bar.filter_entries(&options.filter)? {
+        println!("{:<12} {:<12} {:<2} {}", entry.offset, entry.file_size2, entry.is_encoded, entry.get_file_name()?);
+    }
+    Ok(())
+}
+
+fn print_bar_info(path: PathBuf) -> Result<(), Box<dyn Error>> {
+    let bar = BarFile::open(path)?;
+    let version = match bar.version {
+        BAR_VERSION_AOE3DE => "DE",
+        _ => "Legacy",
+    };
+    let size: u64 = bar.entries.iter().map(|entry| entry.file_size2 as u64).sum();
+    println!("{:<20} {}", "Path", bar.bar_path.display());
+    println!("{:<20} {} ({})", "Version", bar.version, version);
+    println!("{:<20} {}", "Root path", bar.get_root_path()?);
+    println!("{:<20} {}", "File count", bar.file_count);
+    println!("{:<20} {}", "Files table offset", bar.files_table_offset);
+    println!("{:<20} {}", "Size of entries", size);
+    Ok(())
+}
+
+fn verify_bar(path: PathBuf, options: &Options) -> Result<(), Box<dyn Error>> {
+    let bar = BarFile::open(path)?;
+    let failed_entries = bar.verify(options.full_verify)?;
+    for (file_name, error) in &failed_entries {
+        println!("{}: {}", file_name, error);
+    }
+    println!("Checked {} entries, {} broken.", bar.entries.len(), failed_entries.len());
+    if !failed_entries.is_empty() {
+        return Err(ERR_BROKEN_ENTRIES.into());
+    }
+    Ok(())
+}
 
-    println!("{:?}", env::current_exe()?.parent().unwrap());
+// manifest of entry hashes, or differences with another archive or manifest
+fn hash_bar(path: PathBuf, options: &Options) -> Result<(), Box<dyn Error>> {
+    let bar = BarFile::open(path)?;
+    let hashes = bar.hash_entries(&options.filter)?;
+    let compare_path = match &options.compare {
+        Some(compare_path) => compare_path,
+        None => {
+            print!("{}", hash::to_manifest(&hashes));
+            return Ok(());
+        }
+    };
+    let old_hashes = if read_signature(compare_path)? == BINARY_SIGNATURE_BAR {
+        BarFile::open(compare_path.clone())?.hash_entries(&options.filter)?
+    }
+    else {
+        hash::read_manifest(&fs::read_to_string(compare_path)?)
+    };
+    let differences = hash::compare(&old_hashes, &hashes);
+    for difference in &differences {
+        match difference {
+            HashDifference::Added(name) => println!("+ {}", name),
+            HashDifference::Removed(name) => println!("- {}", name),
+            HashDifference::Changed(name) => println!("* {}", name),
+        }
+    }
+    println!("Compared {} entries with {}, {} differ.", hashes.len(), old_hashes.len(), differences.len());
+    Ok(())
+}
+
+fn extract_bar(path: PathBuf, options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut bar = BarFile::open(path)?;
+    bar.trust_entry_paths = options.trust_paths;
+    match options.threads {
+        Some(threads) => bar.multiextract(&options.managed_path, threads, &options.filter)?,
+        None => bar.extract(&options.managed_path, &options.filter)?,
+    }
+    Ok(())
+}
+
+// decode, encode or convert single file to converted folder. false if file is not supported
+fn convert_file(path: PathBuf, options: &Options) -> Result<bool, Box<dyn Error>> {
+    Ok(convert::convert_file(&path, &options.managed_path.join("converted"), options.compress_xmb)?)
+}
+
+// open folder with platform file manager. failure is not fatal
+fn open_in_file_manager(path: &PathBuf) {
+    let opener = if cfg!(target_os = "windows") {
+        "explorer"
+    }
+    else if cfg!(target_os = "macos") {
+        "open"
+    }
+    else {
+        "xdg-open"
+    };
+    if let Err(error) = Command::new(opener).arg(path).spawn() {
+        eprintln!("{} {}: {}", ERR_FILE_MANAGER_NOT_STARTED, opener, error);
+    }
+}
+
+// drag-and-drop behaviour: action is selected by path type and file signature.
+// returns true if something was written to managed folder
+fn process_path(path: PathBuf, options: &Options) -> Result<bool, Box<dyn Error>> {
+    if !path.exists() {
+        print_help();
+    }
+    else if path.is_dir() {
+        BarFile::create(path, &options.managed_path, options.bar_version, &options.compress)?;
+        return Ok(true);
+    }
+    else if read_signature(&path)? == BINARY_SIGNATURE_BAR {
+        extract_bar(path, options)?;
+        return Ok(true);
+    }
+    else if convert_file(path, options)? {
+        return Ok(true);
+    }
+    else {
+        print_help();
+    }
+    Ok(false)
+}
+
+// returns true if something was written to managed folder
+fn run_command(command: &str, path: PathBuf, options: &Options) -> Result<bool, Box<dyn Error>> {
+    match command {
+        "list" => {
+            list_bar(path, options)?;
+            return Ok(false);
+        },
+        "extract" => extract_bar(path, options)?,
+        "create" => {
+            BarFile::create(path, &options.managed_path, options.bar_version, &options.compress)?;
+        },
+        "convert" => {
+            if !convert_file(path, options)? {
+                return Err(ERR_NOT_SUPPORTED_FILE.into());
+            }
+        },
+        "info" => {
+            print_bar_info(path)?;
+            return Ok(false);
+        },
+        "verify" => {
+            verify_bar(path, options)?;
+            return Ok(false);
+        },
+        "hash" => {
+            hash_bar(path, options)?;
+            return Ok(false);
+        },
+        _ => {
+            print_help();
+            return Ok(false);
+        },
+    }
+    Ok(true)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+
+    // diagnostics go to stderr, so output of list and hash can be redirected to file
+    eprintln!("{:?}", env::current_exe()?.parent().unwrap());
 
     let start = Instant::now();
+    let options: Options;
+    let has_output: bool;
     match args.len() {
-        2 => {
-            let path = PathBuf::from(&args[1]);
-            if path.exists() {
-                if path.is_dir() {
-                    BarFile::create(path, BAR_VERSION_AOE3DE)?;
-                }
-                else {
-                    let file = File::open(&path)?;
-                    let mut reader = BufReader::new(file);
-                    let mut signature = [0u8; 4];
-                    reader.read_exact(&mut signature)?;
-                    let signature: u32 = u32::from_le_bytes(signature);
-                    reader.seek(SeekFrom::Start(0))?;
-                    match signature {
-                        BINARY_SIGNATURE_BAR => {
-                            let bar = BarFile::open(path)?;
-                            bar.extract()?;
-                        },
-                        BINARY_SIGNATURE_DDT => {
-                            let managed_path = env::current_dir()?.join("managed").join("timing");
-                            let mut converted_path = managed_path.join("converted").join(path.file_name().unwrap());
-                            let prefix = converted_path.parent().unwrap();
-                            fs::create_dir_all(prefix)?;
-                            let mut data: Vec<u8> = Vec::new();
-                            reader.read_to_end(&mut data)?;
-                            converted_path.set_extension("tga");
-                            let ddt_file = DdtFile::read(&data)?;
-                            let tga_file = ddt_file.to_tga()?;
-                            tga_file.save(converted_path)?;
-
-                            Command::new("explorer")
-                                .arg(managed_path)
-                                .spawn()
-                                .unwrap();
-                        },
-                        BINARY_SIGNATURE_WAV_ENCODED => {
-                            let managed_path = env::current_dir()?.join("managed").join("timing");
-                            let converted_path = managed_path.join("converted").join(path.file_name().unwrap());
-                            let prefix = converted_path.parent().unwrap();
-                            fs::create_dir_all(prefix)?;
-                            let mut data: Vec<u8> = Vec::new();
-                            reader.read_to_end(&mut data)?;
-                            let decoded_data: Vec<u8> = BarFile::decode_sound(&data)?;
-                            let mut writer = BufWriter::new(File::create(&converted_path)?);
-                            writer.write(&decoded_data)?; 
-                            Command::new("explorer")
-                            .arg(managed_path)
-                            .spawn()
-                            .unwrap(); 
-                        },
-                        BINARY_SIGNATURE_ALZ4 => {
-
-                        },
-                        BINARY_SIGNATURE_L33T => {
-
-                        },
-                        _ => {
-                            print_help();
-                        }
-                    }
-                }
-            }
-            else {
-                print_help();
-            }
+        len if len >= 3 && COMMANDS.contains(&args[1].as_str()) => {
+            options = parse_options(&args[3..], OUTPUT_COMMANDS.contains(&args[1].as_str()))?;
+            has_output = run_command(&args[1], PathBuf::from(&args[2]), &options)?;
+        },
+        len if len >= 2 => {
+            options = parse_options(&args[2..], true)?;
+            has_output = process_path(PathBuf::from(&args[1]), &options)?;
         },
         _ => {
+            options = parse_options(&[], false)?;
+            has_output = false;
             print_help();
         }
     }
     let end = Instant::now();
-    println!("Elapsed time: {:?}", end - start);
-    println!("{}", "Press any key to exit...");
-    let mut line: String = String::new();
-    stdin().read_line(&mut line)?;
+    eprintln!("Elapsed time: {:?}", end - start);
+
+    if has_output && (options.open || !options.headless) {
+        open_in_file_manager(&options.managed_path);
+    }
+    // prompt must not get into redirected output of list or hash, and nobody sees it there
+    if !options.headless && stdout().is_terminal() {
+        eprintln!("Press any key to exit...");
+        let mut line: String = String::new();
+        stdin().read_line(&mut line)?;
+    }
     Ok(())
 }
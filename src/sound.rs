@@ -0,0 +1,83 @@
+use crate::consts::BINARY_SIGNATURE_WAV_DECODED;
+#[cfg(test)]
+use crate::consts::BINARY_SIGNATURE_WAV_ENCODED;
+
+use crate::error::{ResourceError, FileFormat};
+
+// xor data with sound keystream. keystream doesn't depend on data, so same operation encodes and decodes
+fn apply_keystream(source: &[u8]) -> Vec<u8> {
+    let qword8: u64 = 0x23966BA95E28C33F;
+    let qword10: u64 = 0x39BAE3441DB35873;
+    let mut qword18: u64 = 0x2AF92545ADDE0B65;
+
+    let mut decoded_data:Vec<u8> = Vec::new();
+    let non_padded_size = source.len();
+    let padding_length = (8 - non_padded_size % 8) % 8;
+    let padded_data = &mut source.to_vec();
+
+    if padding_length > 0 {
+        padded_data.resize(non_padded_size + padding_length, 0);
+    }
+    let mut padded_data = &padded_data[..];
+
+    while padded_data.len() >= 8 {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&padded_data[..8]);
+        let buf_value: u64 = u64::from_le_bytes(buf);    
+        
+        qword18 = (qword10.wrapping_mul(qword18.wrapping_add(qword8))).rotate_left(32);
+        buf = (buf_value ^ qword18).to_le_bytes();
+
+        decoded_data.extend_from_slice(&buf);
+        padded_data = &padded_data[8..];   
+    }
+
+    decoded_data.resize(non_padded_size, 0);
+    decoded_data
+}
+
+fn get_signature(data: &[u8]) -> u32 {
+    if data.len() >= 4 { u32::from_le_bytes([data[0], data[1], data[2], data[3]]) } else { 0 }
+}
+
+// decode encrypted sound file (xor with keystream), result is RIFF WAV
+pub fn decode(source: &[u8]) -> Result<Vec<u8>, ResourceError> {
+    let decoded_data = apply_keystream(source);
+    let signature: u32 = get_signature(&decoded_data);
+    if signature != BINARY_SIGNATURE_WAV_DECODED {
+        return Err(ResourceError::BadSignature { format: FileFormat::Wav, signature });
+    }
+    Ok(decoded_data)
+}
+
+// RIFF container with WAVE form. AVI and WebP are RIFF too
+pub fn is_wav(data: &[u8]) -> bool {
+    get_signature(data) == BINARY_SIGNATURE_WAV_DECODED && data.len() >= 12 && &data[8..12] == b"WAVE"
+}
+
+// encrypt RIFF WAV to sound format of de archives
+pub fn encode(source: &[u8]) -> Result<Vec<u8>, ResourceError> {
+    let signature: u32 = get_signature(source);
+    if signature != BINARY_SIGNATURE_WAV_DECODED {
+        return Err(ResourceError::BadSignature { format: FileFormat::Wav, signature });
+    }
+    Ok(apply_keystream(source))
+}
+
+#[test]
+fn decode_broken_sound() {
+    assert!(matches!(decode(b"RIF"), Err(ResourceError::BadSignature { format: FileFormat::Wav, signature: 0 })));
+    assert!(matches!(decode(b"RIFF0000WAVE"), Err(ResourceError::BadSignature { format: FileFormat::Wav, .. })));
+}
+
+#[test]
+fn encode_sound() {
+    let wav = b"RIFF\x24\x00\x00\x00WAVEfmt \x10\x00\x00\x00\x01\x00\x01\x00".to_vec();
+    let encoded = encode(&wav).unwrap();
+    assert_eq!(get_signature(&encoded), BINARY_SIGNATURE_WAV_ENCODED);
+    assert_eq!(encoded.len(), wav.len());
+    assert_eq!(decode(&encoded).unwrap(), wav);
+    assert!(matches!(encode(&encoded), Err(ResourceError::BadSignature { format: FileFormat::Wav, .. })));
+    assert!(is_wav(&wav));
+    assert!(!is_wav(b"RIFF\x04\x00\x00\x00WEBPVP8 "));
+}
@@ -0,0 +1,437 @@
+use crate::consts::BINARY_SIGNATURE_XMB;
+use crate::consts::BINARY_SIGNATURE_XMB_ROOT;
+use crate::consts::BINARY_SIGNATURE_XMB_NODE;
+
+use crate::alz4;
+
+use crate::error::{ResourceError, FileFormat};
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    fs::File,
+    io::{Read, Write, Cursor, BufWriter},
+};
+
+// xmb versions with line numbers in nodes
+const XMB_VERSION_LINE_NUMBERS: u32 = 8;
+const XMB_VERSION: u32 = 8;
+const XMB_UNK1: u32 = 4;
+// deeper trees are treated as corrupted instead of overflowing the stack
+const XMB_MAX_DEPTH: u32 = 1024;
+
+pub struct XmbNode {
+    pub name: String,
+    pub text: String,
+    pub line_number: u32,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<XmbNode>,
+}
+
+pub struct XmbFile {
+    unk1: u32, // ? always 4
+    pub version: u32,
+    pub root: XmbNode,
+}
+
+// check if data starts with xmb header: X1, length of the rest, XR. text files may start with X1 too
+pub fn is_xmb(data: &[u8]) -> bool {
+    if data.len() < 8 {
+        return false;
+    }
+    let length = u32::from_le_bytes([data[2], data[3], data[4], data[5]]) as u64;
+    u16::from_le_bytes([data[0], data[1]]) == BINARY_SIGNATURE_XMB
+        && u16::from_le_bytes([data[6], data[7]]) == BINARY_SIGNATURE_XMB_ROOT
+        && 6 + length <= data.len() as u64
+}
+
+fn read_u16(reader: &mut Cursor<&[u8]>) -> Result<u16, ResourceError> {
+    let mut value = [0u8; 2];
+    reader.read_exact(&mut value).map_err(|_| ResourceError::CorruptedData(FileFormat::Xmb))?;
+    Ok(u16::from_le_bytes(value))
+}
+
+fn read_u32(reader: &mut Cursor<&[u8]>) -> Result<u32, ResourceError> {
+    let mut value = [0u8; 4];
+    reader.read_exact(&mut value).map_err(|_| ResourceError::CorruptedData(FileFormat::Xmb))?;
+    Ok(u32::from_le_bytes(value))
+}
+
+// u32 length in chars followed by utf-16-le string
+fn read_string(reader: &mut Cursor<&[u8]>) -> Result<String, ResourceError> {
+    let length = read_u32(reader)? as usize;
+    if length as u64 * 2 > reader.get_ref().len() as u64 - reader.position() {
+        return Err(ResourceError::CorruptedData(FileFormat::Xmb));
+    }
+    let mut chars: Vec<u16> = Vec::with_capacity(length);
+    for _ in 0..length {
+        chars.push(read_u16(reader)?);
+    }
+    String::from_utf16(&chars).map_err(|_| ResourceError::CorruptedData(FileFormat::Xmb))
+}
+
+fn write_string(dest: &mut Vec<u8>, value: &str) {
+    let chars: Vec<u16> = value.encode_utf16().collect();
+    dest.extend_from_slice(&(chars.len() as u32).to_le_bytes());
+    for c in chars {
+        dest.extend_from_slice(&c.to_le_bytes());
+    }
+}
+
+// index of name in table, adding it if it is not there yet
+fn name_id(names: &mut Vec<String>, ids: &mut HashMap<String, u32>, name: &str) -> u32 {
+    if let Some(id) = ids.get(name) {
+        return *id;
+    }
+    let id = names.len() as u32;
+    names.push(name.to_owned());
+    ids.insert(name.to_owned(), id);
+    id
+}
+
+fn escape_xml(source: &str, attribute: bool) -> String {
+    let mut dest = String::with_capacity(source.len());
+    for c in source.chars() {
+        match c {
+            '&' => dest.push_str("&amp;"),
+            '<' => dest.push_str("&lt;"),
+            '>' => dest.push_str("&gt;"),
+            '"' if attribute => dest.push_str("&quot;"),
+            '\n' if attribute => dest.push_str("&#10;"),
+            '\r' => dest.push_str("&#13;"),
+            '\t' if attribute => dest.push_str("&#9;"),
+            _ => dest.push(c),
+        }
+    }
+    dest
+}
+
+impl XmbNode {
+    fn read(reader: &mut Cursor<&[u8]>, version: u32, elements: &[String], attributes: &[String], depth: u32) -> Result<XmbNode, ResourceError> {
+        if depth > XMB_MAX_DEPTH {
+            return Err(ResourceError::CorruptedData(FileFormat::Xmb));
+        }
+        if read_u16(reader)? != BINARY_SIGNATURE_XMB_NODE {
+            return Err(ResourceError::CorruptedData(FileFormat::Xmb));
+        }
+        let _length = read_u32(reader)?;
+        let text = read_string(reader)?;
+
+        let name_id = read_u32(reader)? as usize;
+        let name = elements.get(name_id).ok_or(ResourceError::CorruptedData(FileFormat::Xmb))?.to_owned();
+
+        let mut line_number: u32 = 0;
+        if version >= XMB_VERSION_LINE_NUMBERS {
+            line_number = read_u32(reader)?;
+        }
+
+        let attribute_count = read_u32(reader)?;
+        let mut node_attributes: Vec<(String, String)> = Vec::new();
+        for _ in 0..attribute_count {
+            let attribute_id = read_u32(reader)? as usize;
+            let attribute_name = attributes.get(attribute_id).ok_or(ResourceError::CorruptedData(FileFormat::Xmb))?.to_owned();
+            let value = read_string(reader)?;
+            node_attributes.push((attribute_name, value));
+        }
+
+        let child_count = read_u32(reader)?;
+        let mut children: Vec<XmbNode> = Vec::new();
+        for _ in 0..child_count {
+            children.push(XmbNode::read(reader, version, elements, attributes, depth + 1)?);
+        }
+
+        Ok(XmbNode {
+            name,
+            text,
+            line_number,
+            attributes: node_attributes,
+            children,
+        })
+    }
+
+    // text of elements with children is trimmed, so formatting whitespace is not stored
+    fn from_xml(node: roxmltree::Node, document: &roxmltree::Document) -> XmbNode {
+        let mut text = String::new();
+        let mut children: Vec<XmbNode> = Vec::new();
+        for child in node.children() {
+            if child.is_element() {
+                children.push(XmbNode::from_xml(child, document));
+            }
+            else if child.is_text() {
+                text.push_str(child.text().unwrap_or(""));
+            }
+        }
+        if !children.is_empty() {
+            text = text.trim().to_owned();
+        }
+        let attributes: Vec<(String, String)> = node.attributes()
+            .map(|attribute| (attribute.name().to_owned(), attribute.value().to_owned()))
+            .collect();
+
+        XmbNode {
+            name: node.tag_name().name().to_owned(),
+            text,
+            line_number: document.text_pos_at(node.range().start).row,
+            attributes,
+            children,
+        }
+    }
+
+    fn collect_names(&self, elements: &mut Vec<String>, element_ids: &mut HashMap<String, u32>, attributes: &mut Vec<String>, attribute_ids: &mut HashMap<String, u32>) {
+        name_id(elements, element_ids, &self.name);
+        for (name, _) in &self.attributes {
+            name_id(attributes, attribute_ids, name);
+        }
+        for child in &self.children {
+            child.collect_names(elements, element_ids, attributes, attribute_ids);
+        }
+    }
+
+    fn to_bytes(&self, version: u32, element_ids: &HashMap<String, u32>, attribute_ids: &HashMap<String, u32>) -> Vec<u8> {
+        let mut body: Vec<u8> = Vec::new();
+        write_string(&mut body, &self.text);
+        body.extend_from_slice(&element_ids[&self.name].to_le_bytes());
+        if version >= XMB_VERSION_LINE_NUMBERS {
+            body.extend_from_slice(&self.line_number.to_le_bytes());
+        }
+        body.extend_from_slice(&(self.attributes.len() as u32).to_le_bytes());
+        for (name, value) in &self.attributes {
+            body.extend_from_slice(&attribute_ids[name].to_le_bytes());
+            write_string(&mut body, value);
+        }
+        body.extend_from_slice(&(self.children.len() as u32).to_le_bytes());
+        for child in &self.children {
+            body.extend_from_slice(&child.to_bytes(version, element_ids, attribute_ids));
+        }
+
+        let mut bytes: Vec<u8> = Vec::with_capacity(body.len() + 6);
+        bytes.extend_from_slice(&BINARY_SIGNATURE_XMB_NODE.to_le_bytes());
+        bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    fn write_xml(&self, dest: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        dest.push_str(&indent);
+        dest.push('<');
+        dest.push_str(&self.name);
+        for (name, value) in &self.attributes {
+            dest.push(' ');
+            dest.push_str(name);
+            dest.push_str("=\"");
+            dest.push_str(&escape_xml(value, true));
+            dest.push('"');
+        }
+
+        if self.children.is_empty() && self.text.is_empty() {
+            dest.push_str("/>\n");
+            return;
+        }
+        dest.push('>');
+        dest.push_str(&escape_xml(&self.text, false));
+        if !self.children.is_empty() {
+            dest.push('\n');
+            for child in &self.children {
+                child.write_xml(dest, depth + 1);
+            }
+            dest.push_str(&indent);
+        }
+        dest.push_str("</");
+        dest.push_str(&self.name);
+        dest.push_str(">\n");
+    }
+}
+
+impl XmbFile {
+    pub fn read(data: &[u8]) -> Result<XmbFile, ResourceError> {
+        let mut reader = Cursor::new(data);
+        let signature = read_u16(&mut reader)?;
+        if signature != BINARY_SIGNATURE_XMB {
+            return Err(ResourceError::BadSignature { format: FileFormat::Xmb, signature: signature as u32 });
+        }
+        let _data_length = read_u32(&mut reader)?;
+        let signature = read_u16(&mut reader)?;
+        if signature != BINARY_SIGNATURE_XMB_ROOT {
+            return Err(ResourceError::BadSignature { format: FileFormat::Xmb, signature: signature as u32 });
+        }
+        let unk1 = read_u32(&mut reader)?;
+        let version = read_u32(&mut reader)?;
+
+        let element_count = read_u32(&mut reader)?;
+        let mut elements: Vec<String> = Vec::new();
+        for _ in 0..element_count {
+            elements.push(read_string(&mut reader)?);
+        }
+
+        let attribute_count = read_u32(&mut reader)?;
+        let mut attributes: Vec<String> = Vec::new();
+        for _ in 0..attribute_count {
+            attributes.push(read_string(&mut reader)?);
+        }
+
+        let root = XmbNode::read(&mut reader, version, &elements, &attributes, 0)?;
+        Ok(XmbFile {
+            unk1,
+            version,
+            root,
+        })
+    }
+
+    pub fn from_xml(text: &str) -> Result<XmbFile, ResourceError> {
+        let document = roxmltree::Document::parse(text).map_err(|error| ResourceError::InvalidXml(error.to_string()))?;
+        Ok(XmbFile {
+            unk1: XMB_UNK1,
+            version: XMB_VERSION,
+            root: XmbNode::from_xml(document.root_element(), &document),
+        })
+    }
+
+    pub fn open_xml(path: PathBuf) -> Result<XmbFile, ResourceError> {
+        let mut text = String::new();
+        File::open(&path)?.read_to_string(&mut text)?;
+        // skip utf-8 bom
+        XmbFile::from_xml(text.trim_start_matches('\u{feff}'))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut elements: Vec<String> = Vec::new();
+        let mut element_ids: HashMap<String, u32> = HashMap::new();
+        let mut attributes: Vec<String> = Vec::new();
+        let mut attribute_ids: HashMap<String, u32> = HashMap::new();
+        self.root.collect_names(&mut elements, &mut element_ids, &mut attributes, &mut attribute_ids);
+
+        let mut body: Vec<u8> = Vec::new();
+        body.extend_from_slice(&BINARY_SIGNATURE_XMB_ROOT.to_le_bytes());
+        body.extend_from_slice(&self.unk1.to_le_bytes());
+        body.extend_from_slice(&self.version.to_le_bytes());
+        body.extend_from_slice(&(elements.len() as u32).to_le_bytes());
+        for element in &elements {
+            write_string(&mut body, element);
+        }
+        body.extend_from_slice(&(attributes.len() as u32).to_le_bytes());
+        for attribute in &attributes {
+            write_string(&mut body, attribute);
+        }
+        body.extend_from_slice(&self.root.to_bytes(self.version, &element_ids, &attribute_ids));
+
+        let mut bytes: Vec<u8> = Vec::with_capacity(body.len() + 6);
+        bytes.extend_from_slice(&BINARY_SIGNATURE_XMB.to_le_bytes());
+        bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    // save as xmb, optionally wrapped with alz4 for DE
+    pub fn save(&self, path: PathBuf, compress: bool) -> Result<(), ResourceError> {
+        let mut data = self.to_bytes();
+        if compress {
+            data = alz4::compress(&data);
+        }
+        let mut writer = BufWriter::new(File::create(&path)?);
+        writer.write_all(&data)?;
+        Ok(())
+    }
+
+    // proto.xml -> proto.xml.XMB
+    pub fn xmb_path(path: PathBuf) -> PathBuf {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        path.with_file_name(file_name + ".XMB")
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut dest = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        self.root.write_xml(&mut dest, 0);
+        dest
+    }
+
+    pub fn save_xml(&self, path: PathBuf) -> Result<(), ResourceError> {
+        let mut writer = BufWriter::new(File::create(&path)?);
+        writer.write_all(self.to_xml().as_bytes())?;
+        Ok(())
+    }
+
+    // proto.xml.xmb -> proto.xml, sound.xmb -> sound.xml
+    pub fn xml_path(mut path: PathBuf) -> PathBuf {
+        path.set_extension("");
+        let is_xml = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("xml"));
+        if !is_xml {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            path.set_file_name(file_name + ".xml");
+        }
+        path
+    }
+}
+
+
+#[test]
+fn decode_xmb_to_xml() {
+    fn push_string(data: &mut Vec<u8>, value: &str) {
+        let chars: Vec<u16> = value.encode_utf16().collect();
+        data.extend_from_slice(&(chars.len() as u32).to_le_bytes());
+        for c in chars {
+            data.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+    fn push_node(data: &mut Vec<u8>, text: &str, name_id: u32, attributes: &[(u32, &str)], children: &[Vec<u8>]) {
+        let mut body: Vec<u8> = Vec::new();
+        push_string(&mut body, text);
+        body.extend_from_slice(&name_id.to_le_bytes());
+        body.extend_from_slice(&1u32.to_le_bytes());
+        body.extend_from_slice(&(attributes.len() as u32).to_le_bytes());
+        for (id, value) in attributes {
+            body.extend_from_slice(&id.to_le_bytes());
+            push_string(&mut body, value);
+        }
+        body.extend_from_slice(&(children.len() as u32).to_le_bytes());
+        for child in children {
+            body.extend_from_slice(child);
+        }
+        data.extend_from_slice(&BINARY_SIGNATURE_XMB_NODE.to_le_bytes());
+        data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        data.extend_from_slice(&body);
+    }
+
+    let mut unit: Vec<u8> = Vec::new();
+    push_node(&mut unit, "Fish & \"Chips\"", 1, &[(0, "1<2")], &[]);
+    let mut empty: Vec<u8> = Vec::new();
+    push_node(&mut empty, "", 1, &[], &[]);
+    let mut root: Vec<u8> = Vec::new();
+    push_node(&mut root, "", 0, &[], &[unit, empty]);
+
+    let mut body: Vec<u8> = Vec::new();
+    body.extend_from_slice(&BINARY_SIGNATURE_XMB_ROOT.to_le_bytes());
+    body.extend_from_slice(&4u32.to_le_bytes());
+    body.extend_from_slice(&8u32.to_le_bytes());
+    body.extend_from_slice(&2u32.to_le_bytes());
+    push_string(&mut body, "units");
+    push_string(&mut body, "unit");
+    body.extend_from_slice(&1u32.to_le_bytes());
+    push_string(&mut body, "id");
+    body.extend_from_slice(&root);
+
+    let mut data: Vec<u8> = Vec::new();
+    data.extend_from_slice(&BINARY_SIGNATURE_XMB.to_le_bytes());
+    data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    data.extend_from_slice(&body);
+
+    assert!(is_xmb(&data));
+    assert!(!is_xmb(b"X1 is a text file, not xmb"));
+    assert!(!is_xmb(&data[..data.len() - 1]));
+    let xmb_file = XmbFile::read(&data).unwrap();
+    assert_eq!(xmb_file.to_xml(), "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<units>\n  <unit id=\"1&lt;2\">Fish &amp; \"Chips\"</unit>\n  <unit/>\n</units>\n");
+    assert_eq!(XmbFile::xml_path(PathBuf::from("proto.xml.XMB")), PathBuf::from("proto.xml"));
+    assert_eq!(XmbFile::xml_path(PathBuf::from("sound.xmb")), PathBuf::from("sound.xml"));
+}
+
+#[test]
+fn encode_xml_to_xmb() {
+    let xml = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!-- units -->\n<units version=\"2\">\n  <unit id=\"1\" name=\"Musketeer\">\n    <cost resourcetype=\"Food\">50</cost>\n    <flag>&amp;&lt;&quot;</flag>\n    <empty/>\n  </unit>\n  <unit id=\"2\">text<child/></unit>\n</units>\n";
+    let xmb_file = XmbFile::from_xml(xml).unwrap();
+    assert_eq!(xmb_file.root.children[0].line_number, 4);
+    let decoded = XmbFile::read(&xmb_file.to_bytes()).unwrap();
+    assert_eq!(decoded.to_xml(), xmb_file.to_xml());
+    assert_eq!(decoded.root.children[0].children[1].text, "&<\"");
+    assert_eq!(XmbFile::from_xml(&decoded.to_xml()).unwrap().to_xml(), decoded.to_xml());
+    assert_eq!(XmbFile::xmb_path(PathBuf::from("proto.xml")), PathBuf::from("proto.xml.XMB"));
+}